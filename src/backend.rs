@@ -0,0 +1,329 @@
+use std::cell::RefCell;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::daemon::Client;
+use crate::error::TasksError;
+use crate::parser::{AddArgs, EditArgs, ListArgs};
+use crate::store::{choose, Store, Timer};
+use crate::task::{Energy, Estimate, Interval, Task};
+
+/// Arguments of `tasks random`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RandomArgs {
+    /// Only tasks matching these search terms
+    pub terms:  Vec<String>,
+    pub tags:   Vec<String>,
+    pub ntags:  Vec<String>,
+    #[serde(default = "default_n")]
+    pub n:      usize,
+    /// Ignores the cutoff, so that new tasks can be picked
+    pub force:  bool,
+    /// Only tasks estimated to fit in this much time
+    pub time:   Option<Estimate>,
+    /// Only tasks that need at most this much energy
+    pub energy: Option<Energy>,
+}
+
+fn default_n() -> usize {
+    return 1;
+}
+
+/// Where commands are carried out: on the task files, or through a running `tasks serve`
+pub enum Backend {
+    Local { store: Box<Store>, cutoff: u64 },
+    Remote(RefCell<Client>),
+}
+
+impl Backend {
+    /// Goes through the daemon listening on `socket` if there is one, and uses `store` otherwise
+    pub fn open(store: Store, cutoff: u64, socket: &Path) -> Backend {
+        return match Client::connect(socket) {
+            Some(client) => Backend::Remote(RefCell::new(client)),
+            None => Backend::Local {
+                store: Box::new(store),
+                cutoff,
+            },
+        };
+    }
+
+    fn call<T>(&self, method: &str, params: impl Serialize) -> Result<T, TasksError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let Backend::Remote(client) = self else {
+            unreachable!("only remote backends make calls");
+        };
+        return client.borrow_mut().call(method, params);
+    }
+
+    pub fn add(&self, args: &AddArgs) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => {
+                let task = args.task();
+                if !args.force {
+                    let similar = store.resembling(task.name())?;
+                    if !similar.is_empty() {
+                        return Err(TasksError::Duplicate {
+                            name:    task.name().to_string(),
+                            similar: similar.iter().map(|task| task.name().to_string()).collect(),
+                        });
+                    }
+                }
+                store.append(task)
+            },
+            Backend::Remote(_) => self.call("add", args),
+        };
+    }
+
+    /// Active tasks matching `args`, sorted and paged
+    pub fn list(&self, args: &ListArgs) -> Result<Vec<Task>, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => {
+                let mut tasks = store.filter_active(
+                    &args.terms,
+                    &args.tags.clone().unwrap_or_default(),
+                    &args.ntags.clone().unwrap_or_default(),
+                )?;
+                tasks.retain(|task| task.fits(args.time, args.energy));
+                args.arrange(tasks)
+            },
+            Backend::Remote(_) => self.call("list", args),
+        };
+    }
+
+    pub fn random(&self, args: &RandomArgs) -> Result<Vec<Task>, TasksError> {
+        return match self {
+            Backend::Local { store, cutoff } => {
+                let mut items = store.filter_active(&args.terms, &args.tags, &args.ntags)?;
+                items.retain(|task| task.fits(args.time, args.energy));
+                let cutoff = if args.force { 0 } else { *cutoff };
+                store.select_random_from_list(&items, args.n, cutoff)
+            },
+            Backend::Remote(_) => self.call("random", args),
+        };
+    }
+
+    /// The task `tasks hook` shows: the one it showed last, until `interval` has passed
+    pub fn reminder(
+        &self,
+        interval: chrono::Duration,
+        tags: &[String],
+        ntags: &[String],
+    ) -> Result<Option<Task>, TasksError> {
+        return match self {
+            Backend::Local { store, cutoff } => store.reminder(interval, tags, ntags, *cutoff),
+            Backend::Remote(_) => self.call(
+                "reminder",
+                json!({ "interval": interval.num_seconds(), "tags": tags, "ntags": ntags }),
+            ),
+        };
+    }
+
+    /// The task `terms` identify, asking the user to choose when several match
+    pub fn find(&self, terms: &[String]) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.fzf(terms),
+            Backend::Remote(_) => {
+                if let [term] = terms {
+                    if term.eq_ignore_ascii_case("last") {
+                        match self.pick(term) {
+                            Err(TasksError::NotFound(_)) => {},
+                            found => return found,
+                        }
+                    }
+                }
+                let args = ListArgs {
+                    terms: terms.to_vec(),
+                    ..Default::default()
+                };
+                // Through `pick`, so the daemon remembers it as the last task
+                let task = choose(self.list(&args)?)?;
+                self.pick(task.id())
+            },
+        };
+    }
+
+    /// Like `find`, but also finds closed tasks: by id, or by searching both banks at once
+    pub fn find_anywhere(&self, terms: &[String]) -> Result<Task, TasksError> {
+        if let [term] = terms {
+            if term.eq_ignore_ascii_case("last") {
+                return self.find(terms);
+            }
+            match self.get(term) {
+                Err(TasksError::NotFound(_)) => {},
+                found => return found,
+            }
+        }
+        let args = ListArgs {
+            terms: terms.to_vec(),
+            ..Default::default()
+        };
+        let words: Vec<&str> = terms.iter().map(|term| term.as_str()).collect();
+        let mut tasks = self.list(&args)?;
+        tasks.extend(
+            self.closed()?
+                .into_iter()
+                .filter(|task| task.mass_contains(&words)),
+        );
+        return choose(tasks);
+    }
+
+    /// Folds `duplicate` into `keep` and closes it, in one undo step
+    pub fn merge(&self, keep: &Task, duplicate: &Task) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.merge(keep, duplicate),
+            Backend::Remote(_) => self.call(
+                "merge",
+                json!({ "keep": keep.id(), "duplicate": duplicate.id() }),
+            ),
+        };
+    }
+
+    /// The task with exactly this id, active or closed
+    pub fn get(&self, id: &str) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.get(id),
+            Backend::Remote(_) => self.call("get", json!({ "id": id })),
+        };
+    }
+
+    /// Every closed task
+    pub fn closed(&self) -> Result<Vec<Task>, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => Ok(store.get_bank("closed")?.iter().cloned().collect()),
+            Backend::Remote(_) => self.call("closed", json!({})),
+        };
+    }
+
+    /// The active task with exactly this id (or `last`), without prompting
+    pub fn pick(&self, id: &str) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.pick(id),
+            Backend::Remote(_) => self.call("pick", json!({ "id": id })),
+        };
+    }
+
+    pub fn touch(&self, task: &Task) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.update_item(task.clone(), Task::touched),
+            Backend::Remote(_) => self.call("touch", json!({ "id": task.id() })),
+        };
+    }
+
+    /// Appends a note to `task`, keeping its description
+    pub fn note(&self, task: &Task, text: &str) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => {
+                store.update_item(task.clone(), |task| task.noted(text))
+            },
+            Backend::Remote(_) => self.call("note", json!({ "id": task.id(), "text": text })),
+        };
+    }
+
+    pub fn close(&self, task: &Task) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.retire_item(task),
+            Backend::Remote(_) => self.call("close", json!({ "id": task.id() })),
+        };
+    }
+
+    /// Applies `args` to `task`. The identifier in `args` is ignored
+    pub fn edit(&self, task: &Task, args: &EditArgs) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => {
+                store.update_item(task.clone(), |task| args.apply(task))
+            },
+            Backend::Remote(_) => {
+                let mut args = args.clone();
+                args.identifier = vec![task.id().to_string()];
+                self.call("edit", args)
+            },
+        };
+    }
+
+    /// Replaces the active task with the same id as `task` by it
+    pub fn replace(&self, task: &Task) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => {
+                let current = store.pick(task.id())?;
+                store.update_item(current, |_| task.clone())
+            },
+            Backend::Remote(_) => self.call("replace", task),
+        };
+    }
+
+    /// Starts timing `task`, returning the task whose timer was stopped to make way
+    pub fn start(&self, task: &Task) -> Result<Option<Task>, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.start(task),
+            Backend::Remote(_) => self.call("start", json!({ "id": task.id() })),
+        };
+    }
+
+    /// Stops the running timer, returning the task with the time recorded
+    pub fn stop(&self) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.stop(),
+            Backend::Remote(_) => self.call("stop", json!({})),
+        };
+    }
+
+    /// How likely `tasks random` is to pick the task with `id` next, `None` if it is not active
+    pub fn chance(&self, id: &str) -> Result<Option<f64>, TasksError> {
+        return match self {
+            Backend::Local { store, cutoff } => store.chance(id, *cutoff),
+            Backend::Remote(_) => self.call("chance", json!({ "id": id })),
+        };
+    }
+
+    pub fn timer(&self) -> Result<Option<Timer>, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.timer(),
+            Backend::Remote(_) => self.call("timer", json!({})),
+        };
+    }
+
+    /// Logs a focus session on the task with this id, and acts on its outcome
+    pub fn log_session(&self, id: &str, session: &Interval) -> Result<Task, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.log_session(id, session.clone()),
+            Backend::Remote(_) => self.call("log_session", json!({ "id": id, "session": session })),
+        };
+    }
+
+    /// Reverts the latest change, and describes it
+    pub fn undo(&self) -> Result<String, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => Ok(store.undo()?.to_string()),
+            Backend::Remote(_) => self.call("undo", json!({})),
+        };
+    }
+
+    pub fn tags(&self) -> Result<Vec<String>, TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.tags(),
+            Backend::Remote(_) => self.call("tags", json!({})),
+        };
+    }
+
+    /// Saves local changes and lets go of the store lock until the next call. The daemon keeps
+    /// the store to itself
+    pub fn release(&self) -> Result<(), TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.release(),
+            Backend::Remote(_) => Ok(()),
+        };
+    }
+
+    /// Writes back local changes. The daemon saves after every call by itself
+    pub fn save(&self) -> Result<(), TasksError> {
+        return match self {
+            Backend::Local { store, .. } => store.save(),
+            Backend::Remote(_) => Ok(()),
+        };
+    }
+}
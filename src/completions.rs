@@ -0,0 +1,119 @@
+use std::io::{self, Write};
+
+use clap::{CommandFactory, ValueEnum};
+use clap_complete::{generate, Shell};
+
+use crate::parser::Cli;
+
+#[derive(Debug, Clone, Copy, ValueEnum, serde::Deserialize, serde::Serialize)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// What the hidden `__complete` command lists, one value per line
+#[derive(Debug, Clone, Copy, ValueEnum, serde::Deserialize, serde::Serialize)]
+pub enum CompletionKind {
+    /// Ids and names of active tasks
+    Tasks,
+    /// Every tag used by an active task
+    Tags,
+}
+
+// Subcommands (and aliases) whose positional arguments identify tasks
+const TASK_COMMANDS: &str =
+    "done d touch t close f finish complete retire edit e list l show s note n merge start";
+// Options before the subcommand that take a value, which is not the subcommand
+const GLOBAL_VALUE_OPTIONS: &str = "--format --config -t --tags -n --ntags";
+// Options that take tags, across all subcommands
+const TAG_OPTIONS: &str = "-t --tags -n --ntags --tag -a --atag --rtag --stag";
+// Short options that only take tags under `edit`, and mean something else elsewhere
+const EDIT_TAG_OPTIONS: &str = "-r -s";
+
+const BASH: &str = r#"
+_tasks_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" IFS=$'\n'
+    local i sub=""
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        if [[ " __GLOBAL_VALUE_OPTIONS__ " == *" ${COMP_WORDS[i]} "* ]]; then
+            ((i++))
+        elif [[ "${COMP_WORDS[i]}" != -* ]]; then
+            sub="${COMP_WORDS[i]}"
+            break
+        fi
+    done
+    if [[ " __TAG_OPTIONS__ " == *" $prev "* ]] ||
+        [[ -n "$sub" && " edit e " == *" $sub "* && " __EDIT_TAG_OPTIONS__ " == *" $prev "* ]]; then
+        COMPREPLY=($(compgen -W "$(tasks __complete tags 2>/dev/null)" -- "$cur"))
+        return
+    fi
+    if [[ -n "$sub" && "$cur" != -* && " __TASK_COMMANDS__ " == *" $sub "* ]]; then
+        COMPREPLY=($(compgen -W "$(tasks __complete tasks 2>/dev/null)" -- "$cur"))
+        COMPREPLY=($(printf '%q\n' "${COMPREPLY[@]}"))
+        return
+    fi
+    unset IFS
+    _tasks "$@"
+}
+complete -F _tasks_dynamic -o bashdefault -o default tasks
+"#;
+
+const ZSH: &str = r#"
+_tasks_dynamic() {
+    local prev=${words[CURRENT-1]} i sub=""
+    for (( i = 2; i < CURRENT; i++ )); do
+        if [[ " __GLOBAL_VALUE_OPTIONS__ " == *" ${words[i]} "* ]]; then
+            (( i++ ))
+        elif [[ ${words[i]} != -* ]]; then
+            sub=${words[i]}
+            break
+        fi
+    done
+    if [[ " __TAG_OPTIONS__ " == *" $prev "* ]] ||
+        [[ -n $sub && " edit e " == *" $sub "* && " __EDIT_TAG_OPTIONS__ " == *" $prev "* ]]; then
+        compadd -- ${(f)"$(tasks __complete tags 2>/dev/null)"}
+        return
+    fi
+    if [[ -n $sub && $PREFIX != -* && " __TASK_COMMANDS__ " == *" $sub "* ]]; then
+        compadd -- ${(f)"$(tasks __complete tasks 2>/dev/null)"}
+        return
+    fi
+    _tasks "$@"
+}
+compdef _tasks_dynamic tasks
+"#;
+
+const FISH: &str = r#"
+complete -c tasks -n "__fish_seen_subcommand_from __TASK_COMMANDS__" -f -a "(tasks __complete tasks 2>/dev/null)"
+for option in __TAG_OPTIONS__
+    set -l flag (string replace -r '^-+' '' -- $option)
+    if string match -q -- '--*' $option
+        complete -c tasks -l $flag -x -a "(tasks __complete tags 2>/dev/null)"
+    else
+        complete -c tasks -s $flag -x -a "(tasks __complete tags 2>/dev/null)"
+    end
+end
+for option in __EDIT_TAG_OPTIONS__
+    set -l flag (string replace -r '^-+' '' -- $option)
+    complete -c tasks -n "__fish_seen_subcommand_from edit e" -s $flag -x -a "(tasks __complete tags 2>/dev/null)"
+end
+"#;
+
+/// Writes a completion script for `shell`.
+/// clap generates the static part from `Cli`; task and tag values are looked up with `tasks __complete` as you type.
+pub fn write_completions(shell: CompletionShell, out: &mut dyn Write) -> io::Result<()> {
+    let (shell, dynamic) = match shell {
+        CompletionShell::Bash => (Shell::Bash, BASH),
+        CompletionShell::Zsh => (Shell::Zsh, ZSH),
+        CompletionShell::Fish => (Shell::Fish, FISH),
+    };
+    let mut cmd = Cli::command();
+    generate(shell, &mut cmd, "tasks", out);
+    let dynamic = dynamic
+        .replace("__TASK_COMMANDS__", TASK_COMMANDS)
+        .replace("__GLOBAL_VALUE_OPTIONS__", GLOBAL_VALUE_OPTIONS)
+        .replace("__EDIT_TAG_OPTIONS__", EDIT_TAG_OPTIONS)
+        .replace("__TAG_OPTIONS__", TAG_OPTIONS);
+    return out.write_all(dynamic.as_bytes());
+}
@@ -1,30 +1,158 @@
-use std::error::Error;
-use std::fs;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::{env, fmt, fs};
 
 use serde::{Deserialize, Deserializer, Serialize};
+use toml::value::Table;
+use toml::Value;
 
-const PATH: [&'static str; 4] = [
-    "~/.config/tasks/tasks.toml",
-    "~/.config/tasks.toml",
-    "~/.config/tasks/tasks.conf",
-    "~/.config/tasks.conf",
+use crate::error::TasksError;
+use crate::parser::ListArgs;
+use crate::sort::SortKey;
+use crate::store::{Backups, Hooks, Repeats};
+use crate::task::{Energy, Estimate};
+use crate::window::Window;
+
+mod edit;
+
+pub use edit::{set, unset};
+
+const SYSTEM: &str = "/etc/tasks/tasks.toml";
+// Under `$XDG_CONFIG_HOME`, or `~/.config`. The first one found is used
+const USER: [&str; 4] = [
+    "tasks/tasks.toml",
+    "tasks.toml",
+    "tasks/tasks.conf",
+    "tasks.conf",
 ];
+const PROJECT: &str = ".tasks.toml";
+
+/// Where a config value came from, from lowest to highest precedence
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    Default,
+    System(PathBuf),
+    User(PathBuf),
+    /// The nearest `.tasks.toml` in the working directory or its parents
+    Project(PathBuf),
+    /// A `TASKS_*` environment variable
+    Env(String),
+    /// The file passed with `--config`
+    Flag(PathBuf),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::System(path) => write!(f, "system ({})", path.display()),
+            Origin::User(path) => write!(f, "user ({})", path.display()),
+            Origin::Project(path) => write!(f, "project ({})", path.display()),
+            Origin::Env(var) => write!(f, "env ({})", var),
+            Origin::Flag(path) => write!(f, "--config ({})", path.display()),
+        }
+    }
+}
+
+/// The merged configuration, with the layer that set each key
+#[derive(Debug, Clone)]
+pub struct Layers {
+    pub config:  Config,
+    /// Every key, tables flattened to `table.key`, in the order they are shown
+    pub origins: BTreeMap<String, Origin>,
+    table:       Table,
+    // Each file that was read, in order
+    files:       Vec<(Origin, Table)>,
+}
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     #[serde(default = "default_path", deserialize_with = "deserialize_path")]
-    pub task_path: PathBuf,
+    pub task_path:     PathBuf,
     #[serde(default = "default_verbose")]
-    pub debug:     bool,
+    pub debug:         bool,
     #[serde(default = "default_cutoff")]
-    pub cutoff:    u64,
+    pub cutoff:        u64,
+    #[serde(default = "default_snooze")]
+    pub snooze:        u64,
+    #[serde(default = "default_hook_interval")]
+    pub hook_interval: u64,
+    #[serde(default = "default_backup_every")]
+    pub backup_every:  u32,
+    #[serde(default = "default_backup_keep")]
+    pub backup_keep:   usize,
+    /// Random selection holds back tasks suggested in this many of the latest rolls
+    #[serde(default = "default_repeat_rolls")]
+    pub repeat_rolls:  usize,
+    /// and tasks suggested within this many seconds
+    #[serde(default = "default_repeat_window")]
+    pub repeat_window: u64,
+    /// Output templates by command name, with `default` for every other command
+    #[serde(default)]
+    pub formats:       BTreeMap<String, String>,
+    /// Commands that expand to others, e.g. `work = "random --tags work 3"`
+    #[serde(default)]
+    pub aliases:       BTreeMap<String, String>,
+    /// Saved filters, run with `tasks view <name>`
+    #[serde(default)]
+    pub views:         BTreeMap<String, View>,
+    /// Executables run around changes to the store, by hook name, e.g. `pre-add`
+    #[serde(default)]
+    pub hooks:         BTreeMap<String, String>,
+    /// When tasks with a tag can be done, by tag, e.g. `work = "weekdays 9-17"`. Tasks with a
+    /// window of their own ignore these
+    #[serde(default)]
+    pub windows:       BTreeMap<String, Window>,
+}
+
+/// A saved `tasks list` query
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct View {
+    pub terms:   Vec<String>,
+    pub tags:    Vec<String>,
+    pub ntags:   Vec<String>,
+    /// Sort keys, as for `tasks list --sort`
+    pub sort:    Option<String>,
+    pub reverse: bool,
+    pub limit:   Option<usize>,
+    pub offset:  usize,
+    /// Only tasks estimated to fit in this much time
+    pub time:    Option<Estimate>,
+    /// Only tasks that need at most this much energy
+    pub energy:  Option<Energy>,
+    /// Output template, or preset
+    pub format:  Option<String>,
 }
 
 fn default_cutoff() -> u64 {
     return 60 * 60 * 24; // 1 day
 }
 
+fn default_snooze() -> u64 {
+    return 60 * 60 * 24; // 1 day
+}
+
+fn default_hook_interval() -> u64 {
+    return 60 * 60 * 4; // 4 hours
+}
+
+fn default_backup_every() -> u32 {
+    return 50;
+}
+
+fn default_backup_keep() -> usize {
+    return 14;
+}
+
+fn default_repeat_rolls() -> usize {
+    return 3;
+}
+
+fn default_repeat_window() -> u64 {
+    return 60 * 60; // 1 hour
+}
+
 fn expand_path(path: &str) -> PathBuf {
     let path = shellexpand::tilde(path);
     let p = Path::new(&*path).to_path_buf();
@@ -50,52 +178,307 @@ fn default_verbose() -> bool {
 impl Default for Config {
     fn default() -> Self {
         return Config {
-            task_path: default_path(),
-            debug:     default_verbose(),
-            cutoff:    default_cutoff(),
+            task_path:     default_path(),
+            debug:         default_verbose(),
+            cutoff:        default_cutoff(),
+            snooze:        default_snooze(),
+            hook_interval: default_hook_interval(),
+            backup_every:  default_backup_every(),
+            backup_keep:   default_backup_keep(),
+            repeat_rolls:  default_repeat_rolls(),
+            repeat_window: default_repeat_window(),
+            formats:       BTreeMap::new(),
+            aliases:       BTreeMap::new(),
+            views:         BTreeMap::new(),
+            hooks:         BTreeMap::new(),
+            windows:       BTreeMap::new(),
         };
     }
 }
 
 impl Config {
-    pub fn from_path() -> Config {
-        for path in PATH {
-            match Config::from_file(&path) {
-                Ok(conf) => return conf,
-                Err(_error) => {
-                    let e = _error.to_string();
-                    println!("Error in {path} - {e}");
-                    continue;
-                },
+    pub fn backups(&self) -> Backups {
+        return Backups {
+            every: self.backup_every,
+            keep:  self.backup_keep,
+        };
+    }
+
+    pub fn repeats(&self) -> Repeats {
+        return Repeats {
+            rolls:  self.repeat_rolls,
+            window: chrono::Duration::seconds(self.repeat_window as i64),
+        };
+    }
+
+    pub fn hooks(&self) -> Hooks {
+        let commands = self
+            .hooks
+            .iter()
+            .map(|(name, command)| (name.clone(), expand_path(command)))
+            .collect();
+        return Hooks { commands };
+    }
+
+    /// The output template (or preset) for `command`.
+    /// `list` prints one line per task, `hook` a compact line, everything else the full card
+    pub fn format(&self, command: &str) -> &str {
+        if let Some(format) = self.formats.get(command).or(self.formats.get("default")) {
+            return format;
+        }
+        return match command {
+            "list" => "oneline",
+            "hook" => "compact",
+            "show" => "detail",
+            _ => "full",
+        };
+    }
+
+    /// Loads every config layer, see [`Config::layers`]
+    pub fn load(flag: Option<&Path>) -> Result<Config, TasksError> {
+        return Ok(Config::layers(flag)?.config);
+    }
+
+    /// Merges, from lowest to highest precedence, the defaults, `/etc/tasks/tasks.toml`, the
+    /// user's config file, the project's `.tasks.toml`, `TASKS_*` environment variables and the
+    /// `--config` file. Missing files are skipped, but the `--config` file must exist
+    pub fn layers(flag: Option<&Path>) -> Result<Layers, TasksError> {
+        let defaults = defaults()?;
+        let mut layers = Layers {
+            config:  Config::default(),
+            origins: BTreeMap::new(),
+            table:   Table::new(),
+            files:   vec![],
+        };
+        layers.merge(defaults.clone(), &Origin::Default);
+        let mut files = vec![Origin::System(PathBuf::from(SYSTEM))];
+        files.extend(user_file().map(Origin::User));
+        files.extend(project_file().map(Origin::Project));
+        for origin in files {
+            if let Origin::System(path) | Origin::User(path) | Origin::Project(path) = &origin {
+                if path.is_file() {
+                    let table = read_layer(path)?;
+                    layers.merge(table.clone(), &origin);
+                    layers.files.push((origin.clone(), table));
+                }
             }
         }
-        println!("using fallback");
-        return Config::default();
+        let (table, sources) = crate::env::layer(&defaults, env::vars())?;
+        layers.merge(table, &Origin::Env(crate::env::PREFIX.to_string()));
+        for (key, var) in sources {
+            layers.origins.insert(key, Origin::Env(var));
+        }
+        if let Some(path) = flag {
+            let table = read_layer(path)?;
+            let origin = Origin::Flag(path.to_path_buf());
+            layers.merge(table.clone(), &origin);
+            layers.files.push((origin, table));
+        }
+        layers.config = Value::Table(layers.table.clone())
+            .try_into()
+            .map_err(|e| TasksError::parse("config", e))?;
+        return Ok(layers);
     }
-    pub fn from_string(string: &str) -> Result<Config, Box<dyn Error>> {
-        let conf: Config = toml::from_str::<Config>(string)?;
+
+    pub fn from_string(string: &str) -> Result<Config, TasksError> {
+        let conf: Config =
+            toml::from_str::<Config>(string).map_err(|e| TasksError::parse("config", e))?;
         return Ok(conf);
     }
 
-    pub fn from_file(file: &str) -> Result<Config, Box<dyn Error>> {
-        let file = &*shellexpand::full(file)?;
-        let content = fs::read_to_string(file)?;
-        let conf = Config::from_string(&content)?;
+    pub fn from_file(file: &str) -> Result<Config, TasksError> {
+        let file = expand(file)?;
+        let content = fs::read_to_string(&file).map_err(TasksError::io(&file))?;
+        let conf =
+            toml::from_str::<Config>(&content).map_err(|e| TasksError::parse(file.display(), e))?;
         return Ok(conf);
     }
 
-    pub fn to_string<'a>(&self) -> Result<String, Box<dyn Error>> {
-        let s = toml::to_string(self)?;
+    pub fn to_string<'a>(&self) -> Result<String, TasksError> {
+        let s = toml::to_string(self).map_err(|e| TasksError::parse("config", e))?;
         return Ok(s);
     }
 
-    pub fn to_file(&self, path: &str) -> Result<(), Box<dyn Error>> {
-        let path = &*shellexpand::full(path)?;
+    pub fn to_file(&self, path: &str) -> Result<(), TasksError> {
+        let path = expand(path)?;
         let s = self.to_string()?;
-        fs::write(path, s)?;
+        fs::write(&path, s).map_err(TasksError::io(&path))?;
         return Ok(());
     }
 }
+
+impl View {
+    /// The `tasks list` arguments this view stands for
+    pub fn list_args(&self) -> Result<ListArgs, TasksError> {
+        let some = |list: &Vec<String>| Some(list.clone()).filter(|list| !list.is_empty());
+        let sort = match &self.sort {
+            Some(sort) => sort
+                .split(',')
+                .map(|key| key.trim().parse())
+                .collect::<Result<Vec<SortKey>, TasksError>>()?,
+            None => vec![],
+        };
+        return Ok(ListArgs {
+            tags: some(&self.tags),
+            ntags: some(&self.ntags),
+            sort,
+            reverse: self.reverse,
+            limit: self.limit,
+            offset: self.offset,
+            time: self.time,
+            energy: self.energy,
+            terms: self.terms.clone(),
+        });
+    }
+}
+
+impl Layers {
+    /// The merged value of `key`, with `table.key` for keys of a table
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        return match key.split_once('.') {
+            Some((table, key)) => self.table.get(table).and_then(|table| table.get(key)),
+            None => self.table.get(key),
+        };
+    }
+
+    fn merge(&mut self, layer: Table, origin: &Origin) {
+        merge_into(&mut self.table, layer, origin, &mut self.origins, "");
+    }
+
+    /// Renders the merged configuration as TOML, optionally noting where each value came from
+    pub fn show(&self, origin: bool) -> Result<String, TasksError> {
+        return self.show_table(&self.table, "", origin);
+    }
+
+    fn show_table(&self, table: &Table, prefix: &str, origin: bool) -> Result<String, TasksError> {
+        let mut out = String::new();
+        let mut tables = vec![];
+        for (key, value) in table {
+            let path = match prefix {
+                "" => key.clone(),
+                _ => format!("{}.{}", prefix, key),
+            };
+            match value {
+                Value::Table(table) => tables.push((path, table)),
+                _ => out.push_str(&self.line(key, &path, value, origin)?),
+            }
+        }
+        for (path, table) in tables {
+            out.push_str(&format!("\n[{}]\n", path));
+            out.push_str(&self.show_table(table, &path, origin)?);
+        }
+        return Ok(out);
+    }
+
+    // A table set as a whole only has an origin for the table itself
+    fn line(
+        &self,
+        key: &str,
+        path: &str,
+        value: &Value,
+        origin: bool,
+    ) -> Result<String, TasksError> {
+        let mut single = Table::new();
+        single.insert(key.to_string(), value.clone());
+        let line = toml::to_string(&single).map_err(|e| TasksError::parse("config", e))?;
+        let line = line.trim_end();
+        let mut from = self.origins.get(path);
+        let mut parent = path;
+        while let (None, Some((up, _))) = (from, parent.rsplit_once('.')) {
+            parent = up;
+            from = self.origins.get(parent);
+        }
+        return Ok(match (origin, from) {
+            (true, Some(from)) => format!("{:<40} # {}\n", line, from),
+            _ => format!("{}\n", line),
+        });
+    }
+}
+
+// Tables are merged key by key, anything else replaces the lower layer's value
+fn merge_into(
+    into: &mut Table,
+    layer: Table,
+    origin: &Origin,
+    origins: &mut BTreeMap<String, Origin>,
+    prefix: &str,
+) {
+    for (key, value) in layer {
+        let path = match prefix {
+            "" => key.clone(),
+            _ => format!("{}.{}", prefix, key),
+        };
+        match (into.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(table)) => {
+                merge_into(existing, table, origin, origins, &path);
+            },
+            (_, value) => {
+                into.insert(key, value);
+                origins.insert(path, origin.clone());
+            },
+        }
+    }
+}
+
+// A relative `task_path` is relative to the file that sets it
+fn read_layer(path: &Path) -> Result<Table, TasksError> {
+    let content = fs::read_to_string(path).map_err(TasksError::io(path))?;
+    let mut table: Table =
+        toml::from_str(&content).map_err(|e| TasksError::parse(path.display(), e))?;
+    if let Some(Value::String(task_path)) = table.get("task_path") {
+        let expanded = expand_path(task_path);
+        if expanded.is_relative() {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            let joined = dir.join(expanded).to_string_lossy().to_string();
+            table.insert("task_path".to_string(), Value::String(joined));
+        }
+    }
+    return Ok(table);
+}
+
+fn defaults() -> Result<Table, TasksError> {
+    return match Value::try_from(Config::default()) {
+        Ok(Value::Table(table)) => Ok(table),
+        _ => Err(TasksError::parse("config", "defaults are not a table")),
+    };
+}
+
+fn user_dir() -> PathBuf {
+    return match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => expand_path("~/.config"),
+    };
+}
+
+fn user_file() -> Option<PathBuf> {
+    let dir = user_dir();
+    return USER
+        .iter()
+        .map(|file| dir.join(file))
+        .find(|path| path.is_file());
+}
+
+/// The file `tasks config set|unset|edit` change: the `--config` file if there is one, otherwise
+/// the user's config file, which does not have to exist yet
+pub fn target(flag: Option<&Path>) -> PathBuf {
+    if let Some(path) = flag {
+        return path.to_path_buf();
+    }
+    return user_file().unwrap_or_else(|| user_dir().join(USER[0]));
+}
+
+fn project_file() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    return cwd
+        .ancestors()
+        .map(|dir| dir.join(PROJECT))
+        .find(|path| path.is_file());
+}
+
+fn expand(path: &str) -> Result<PathBuf, TasksError> {
+    let expanded = shellexpand::full(path).map_err(|e| TasksError::parse(path, e))?;
+    return Ok(PathBuf::from(&*expanded));
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,4 +488,19 @@ mod tests {
         let s = "";
         assert_eq!(s, s)
     }
+
+    #[test]
+    fn views() {
+        let conf = Config::from_string(
+            "[views.chores]\ntags = ['chores']\nsort = 'priority, -age'\nlimit = 5\n",
+        )
+        .unwrap();
+        let args = conf.views["chores"].list_args().unwrap();
+        assert_eq!(args.tags, Some(vec!["chores".to_string()]));
+        assert_eq!(args.ntags, None);
+        assert_eq!(args.sort.len(), 2);
+        assert_eq!(args.limit, Some(5));
+
+        assert!(Config::from_string("[views.chores]\ntag = ['chores']\n").is_err());
+    }
 }
@@ -0,0 +1,209 @@
+use std::fs;
+use std::path::Path;
+
+use toml::Value;
+use toml_edit::{DocumentMut, Item};
+
+use super::{defaults, Config, Layers};
+use crate::env::convert;
+use crate::error::TasksError;
+use crate::store::HOOKS;
+
+// Tables whose entries are tables of their own, which cannot be set from a single value
+const NESTED: [&str; 1] = ["views"];
+
+/// Sets `key` (`table.key` for keys of a table) in the config file at `path`, creating it if
+/// needed. Comments and formatting of the rest of the file are kept
+pub fn set(path: &Path, key: &str, raw: &str) -> Result<(), TasksError> {
+    if let Some((table, _)) = key
+        .split_once('.')
+        .filter(|(table, _)| NESTED.contains(table))
+    {
+        return Err(TasksError::parse(
+            key,
+            format!(
+                "`{}` entries are tables, edit them in the config file",
+                table
+            ),
+        ));
+    }
+    let value = convert(key, raw, &default_for(key)?)?;
+    let mut value = match value {
+        Value::Integer(i) => toml_edit::Value::from(i),
+        Value::Boolean(b) => toml_edit::Value::from(b),
+        value => toml_edit::Value::from(value.as_str().unwrap_or(raw)),
+    };
+    let mut document = read_document(path)?;
+    let slot = match key.split_once('.') {
+        Some((table, key)) => &mut document.entry(table).or_insert(toml_edit::table())[key],
+        None => &mut document[key],
+    };
+    // Keeps a comment on the same line as the old value
+    if let Some(old) = slot.as_value() {
+        *value.decor_mut() = old.decor().clone();
+    }
+    *slot = Item::Value(value);
+    // Caught here rather than by every command that loads the config afterwards
+    Config::from_string(&document.to_string()).map_err(|error| TasksError::parse(key, error))?;
+    return write_document(path, &document);
+}
+
+/// Removes `key` from the config file at `path`, returning whether it was set there
+pub fn unset(path: &Path, key: &str) -> Result<bool, TasksError> {
+    default_for(key)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    let mut document = read_document(path)?;
+    let removed = match key.split_once('.') {
+        Some((table, key)) => document
+            .get_mut(table)
+            .and_then(|table| table.as_table_like_mut())
+            .and_then(|table| table.remove(key)),
+        None => document.remove(key),
+    };
+    if removed.is_none() {
+        return Ok(false);
+    }
+    write_document(path, &document)?;
+    return Ok(true);
+}
+
+impl Layers {
+    /// Unknown keys in any config file, unknown or missing hooks, and whether `task_path` can be
+    /// written to
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let defaults = defaults().unwrap_or_default();
+        for (origin, table) in &self.files {
+            for (key, value) in table {
+                match (defaults.get(key), value) {
+                    (None, _) => problems.push(format!("unknown key `{}` in {}", key, origin)),
+                    (Some(Value::Table(_)), value) if !value.is_table() => {
+                        problems.push(format!("`{}` in {} should be a table", key, origin))
+                    },
+                    _ => {},
+                }
+            }
+        }
+        for name in self.config.hooks.keys() {
+            if !HOOKS.contains(&name.as_str()) {
+                problems.push(format!("unknown hook `{}`", name));
+            }
+        }
+        for (name, command) in self.config.hooks().commands {
+            if !command.is_file() {
+                problems.push(format!(
+                    "{} hook {} does not exist",
+                    name,
+                    command.display()
+                ));
+            }
+        }
+        if let Err(error) = writable(&self.config.task_path) {
+            problems.push(format!(
+                "task_path {} is not writable: {}",
+                self.config.task_path.display(),
+                error
+            ));
+        }
+        return problems;
+    }
+}
+
+// The type a key is parsed as. Keys of a table that has no defaults are strings
+fn default_for(key: &str) -> Result<Value, TasksError> {
+    let defaults = defaults()?;
+    let unknown = || TasksError::NotFound(format!("Unknown config key `{}`", key));
+    return match key.split_once('.') {
+        Some((table, _)) if NESTED.contains(&table) => Err(TasksError::parse(
+            key,
+            format!(
+                "`{}` entries are tables, edit them in the config file",
+                table
+            ),
+        )),
+        Some((table, key)) => match defaults.get(table) {
+            Some(Value::Table(table)) => Ok(table
+                .get(key)
+                .cloned()
+                .unwrap_or(Value::String(String::new()))),
+            _ => Err(unknown()),
+        },
+        None => match defaults.get(key) {
+            Some(Value::Table(_)) => Err(TasksError::parse(
+                key,
+                format!("is a table, set its keys with `{}.<key>`", key),
+            )),
+            Some(value) => Ok(value.clone()),
+            None => Err(unknown()),
+        },
+    };
+}
+
+fn read_document(path: &Path) -> Result<DocumentMut, TasksError> {
+    if !path.exists() {
+        return Ok(DocumentMut::new());
+    }
+    let content = fs::read_to_string(path).map_err(TasksError::io(path))?;
+    return content
+        .parse()
+        .map_err(|e| TasksError::parse(path.display(), e));
+}
+
+fn write_document(path: &Path, document: &DocumentMut) -> Result<(), TasksError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(TasksError::io(dir))?;
+    }
+    return fs::write(path, document.to_string()).map_err(TasksError::io(path));
+}
+
+// The store is created on first use, so a missing directory only needs a writable parent
+fn writable(path: &Path) -> Result<(), String> {
+    let Some(dir) = path.ancestors().find(|dir| dir.exists()) else {
+        return Err("no parent directory exists".to_string());
+    };
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    let probe = dir.join(format!(".tasks-validate-{}", std::process::id()));
+    fs::File::create(&probe).map_err(|e| e.to_string())?;
+    let _ = fs::remove_file(&probe);
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_keeps_comments() {
+        let dir = std::env::temp_dir().join(format!("tasks-config-{}", std::process::id()));
+        let path = dir.join("tasks.toml");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "# my tasks\ncutoff = 5 # seconds\n").unwrap();
+
+        set(&path, "cutoff", "60").unwrap();
+        set(&path, "formats.list", "compact").unwrap();
+        assert!(set(&path, "cutof", "1").is_err());
+        assert!(set(&path, "debug", "maybe").is_err());
+        assert!(set(&path, "views.x", "y").is_err());
+        assert!(set(&path, "windows.work", "someday").is_err());
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(
+            content.starts_with("# my tasks\ncutoff = 60 # seconds\n"),
+            "{}",
+            content
+        );
+        assert!(
+            content.contains("[formats]\nlist = \"compact\""),
+            "{}",
+            content
+        );
+
+        assert!(unset(&path, "formats.list").unwrap());
+        assert!(!unset(&path, "formats.list").unwrap());
+        assert!(Config::from_string(&fs::read_to_string(&path).unwrap()).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,409 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{fs, thread};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::backend::{Backend, RandomArgs};
+use crate::error::TasksError;
+use crate::parser::EditArgs;
+use crate::task::Interval;
+
+mod client;
+mod http;
+
+pub use client::Client;
+
+/// Name of the socket `tasks serve` listens on by default, in the task directory
+pub const SOCKET: &str = "socket";
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Any error from the store. `data.kind` says which
+pub const TASKS_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id:     Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    jsonrpc: String,
+    id:      Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result:  Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error:   Option<RpcError>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RpcError {
+    code:    i64,
+    message: String,
+    #[serde(default)]
+    data:    Value,
+}
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ReminderParams {
+    /// Seconds
+    interval: i64,
+    #[serde(default)]
+    tags:     Vec<String>,
+    #[serde(default)]
+    ntags:    Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct NoteParams {
+    id:   String,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct MergeParams {
+    keep:      String,
+    duplicate: String,
+}
+
+#[derive(Deserialize)]
+struct SessionParams {
+    id:      String,
+    session: Interval,
+}
+
+// What kind of error this is, and its details, for clients to tell them apart
+fn describe(error: &TasksError) -> Value {
+    return match error {
+        TasksError::NotFound(_) => json!({ "kind": "not_found" }),
+        TasksError::Ambiguous { matches, reason } => {
+            json!({ "kind": "ambiguous", "matches": matches, "reason": reason })
+        },
+        TasksError::Parse { what, message } => {
+            json!({ "kind": "parse", "what": what, "reason": message })
+        },
+        TasksError::Hook { hook, message } => {
+            json!({ "kind": "hook", "hook": hook, "reason": message })
+        },
+        TasksError::Duplicate { name, similar } => {
+            json!({ "kind": "duplicate", "name": name, "similar": similar })
+        },
+        TasksError::InvalidBank(_) => json!({ "kind": "invalid_bank" }),
+        TasksError::Locked(path) => json!({ "kind": "locked", "path": path }),
+        TasksError::Io { path, .. } => json!({ "kind": "io", "path": path }),
+        TasksError::Terminal(_) => json!({ "kind": "io" }),
+    };
+}
+
+impl From<TasksError> for RpcError {
+    fn from(error: TasksError) -> RpcError {
+        return RpcError {
+            code:    TASKS_ERROR,
+            message: error.to_string(),
+            data:    describe(&error),
+        };
+    }
+}
+
+/// Serves `backend` over newline delimited JSON-RPC 2.0 on a Unix socket, until a `shutdown`
+/// call. The store stays loaded and locked the whole time, so every write goes through here.
+///
+/// Methods are `add`, `list`, `random`, `reminder`, `get`, `pick`, `touch`, `note`, `close`,
+/// `edit`, `replace`, `closed`, `start`, `stop`, `timer`, `log_session`, `chance`, `merge`, `undo`,
+/// `tags` and `shutdown`. `add`, `list` and `edit` take the same fields as the command line
+/// options, `random` takes `terms`, `tags`, `ntags`, `n`, `force`, `time` and `energy`,
+/// `reminder` an `interval` in seconds with `tags` and `ntags`, `replace` a task,
+/// `log_session` an `id` and a `session` interval, `note` an `id` and the `text`, and `merge` the
+/// ids to `keep` and of the `duplicate`. `get`, `pick`, `touch`, `close`, `start` and `chance` take
+/// an `id`.
+///
+/// With `http`, the same store is also served as a REST API on that address
+pub fn serve(backend: Backend, socket: &Path, http: Option<SocketAddr>) -> Result<(), TasksError> {
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(TasksError::Locked(socket.to_path_buf()));
+        }
+        // Left behind by a daemon that did not shut down cleanly
+        fs::remove_file(socket).map_err(TasksError::io(socket))?;
+    }
+    // Takes the store lock before accepting anyone
+    backend.tags()?;
+    let listener = UnixListener::bind(socket).map_err(TasksError::io(socket))?;
+    println!("Serving tasks on {}", socket.display());
+
+    let backend = Arc::new(Mutex::new(backend));
+    let http = match http {
+        Some(address) => Some(http::spawn(backend.clone(), address)?),
+        None => None,
+    };
+    let stop = Arc::new(AtomicBool::new(false));
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let backend = backend.clone();
+        let stop = stop.clone();
+        let socket = socket.to_path_buf();
+        thread::spawn(move || {
+            if let Err(error) = handle(&backend, stream, &stop) {
+                eprintln!("{}", error);
+            }
+            // Wakes up the listener, so that it sees `stop`
+            if stop.load(Ordering::SeqCst) {
+                let _ = UnixStream::connect(&socket);
+            }
+        });
+    }
+    let _ = fs::remove_file(socket);
+    if let Some(http) = http {
+        http.unblock();
+    }
+    // Saves, and runs any outstanding post hooks
+    let backend = backend
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    return backend.save();
+}
+
+fn handle(
+    backend: &Mutex<Backend>,
+    stream: UnixStream,
+    stop: &AtomicBool,
+) -> Result<(), std::io::Error> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let backend = backend
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let result = dispatch(&backend, &request.method, request.params)
+                    .and_then(|result| backend.save().map(|_| result).map_err(RpcError::from));
+                if request.method == "shutdown" {
+                    stop.store(true, Ordering::SeqCst);
+                }
+                respond(request.id, result)
+            },
+            Err(error) => respond(
+                Value::Null,
+                Err(RpcError {
+                    code:    PARSE_ERROR,
+                    message: error.to_string(),
+                    data:    Value::Null,
+                }),
+            ),
+        };
+        writer.write_all(serde_json::to_string(&response)?.as_bytes())?;
+        writer.write_all(b"\n")?;
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    return Ok(());
+}
+
+fn respond(id: Value, result: Result<Value, RpcError>) -> Response {
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    return Response {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result,
+        error,
+    };
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    // Methods without parameters may be called without `params`
+    let params = match params {
+        Value::Null => json!({}),
+        params => params,
+    };
+    return serde_json::from_value(params).map_err(|e| RpcError {
+        code:    INVALID_PARAMS,
+        message: e.to_string(),
+        data:    Value::Null,
+    });
+}
+
+fn dispatch(backend: &Backend, method: &str, raw: Value) -> Result<Value, RpcError> {
+    let result = match method {
+        "add" => to_value(backend.add(&params(raw)?)),
+        "list" => to_value(backend.list(&params(raw)?)),
+        "random" => to_value(backend.random(&params::<RandomArgs>(raw)?)),
+        "reminder" => {
+            let ReminderParams {
+                interval,
+                tags,
+                ntags,
+            } = params(raw)?;
+            let interval = chrono::Duration::seconds(interval);
+            to_value(backend.reminder(interval, &tags, &ntags))
+        },
+        "get" => to_value(backend.get(&params::<IdParams>(raw)?.id)),
+        "pick" => to_value(backend.pick(&params::<IdParams>(raw)?.id)),
+        "touch" => {
+            let id = params::<IdParams>(raw)?.id;
+            to_value(backend.pick(&id).and_then(|task| backend.touch(&task)))
+        },
+        "note" => {
+            let NoteParams { id, text } = params(raw)?;
+            to_value(
+                backend
+                    .pick(&id)
+                    .and_then(|task| backend.note(&task, &text)),
+            )
+        },
+        "close" => {
+            let id = params::<IdParams>(raw)?.id;
+            to_value(backend.pick(&id).and_then(|task| backend.close(&task)))
+        },
+        "edit" => {
+            let args: EditArgs = params(raw)?;
+            let id = args.identifier.join(" ");
+            to_value(
+                backend
+                    .pick(&id)
+                    .and_then(|task| backend.edit(&task, &args)),
+            )
+        },
+        "start" => {
+            let id = params::<IdParams>(raw)?.id;
+            to_value(backend.pick(&id).and_then(|task| backend.start(&task)))
+        },
+        "stop" => to_value(backend.stop()),
+        "log_session" => {
+            let SessionParams { id, session } = params(raw)?;
+            to_value(backend.log_session(&id, &session))
+        },
+        "timer" => to_value(backend.timer()),
+        "chance" => to_value(backend.chance(&params::<IdParams>(raw)?.id)),
+        "merge" => {
+            let MergeParams { keep, duplicate } = params(raw)?;
+            to_value(
+                backend
+                    .pick(&keep)
+                    .and_then(|keep| Ok((keep, backend.pick(&duplicate)?)))
+                    .and_then(|(keep, duplicate)| backend.merge(&keep, &duplicate)),
+            )
+        },
+        "replace" => to_value(backend.replace(&params(raw)?)),
+        "closed" => to_value(backend.closed()),
+        "undo" => to_value(backend.undo()),
+        "tags" => to_value(backend.tags()),
+        "shutdown" => Ok(Value::Null),
+        _ => {
+            return Err(RpcError {
+                code:    METHOD_NOT_FOUND,
+                message: format!("Unknown method `{}`", method),
+                data:    Value::Null,
+            })
+        },
+    };
+    return result.map_err(RpcError::from);
+}
+
+fn to_value<T: Serialize>(result: Result<T, TasksError>) -> Result<Value, TasksError> {
+    return result.and_then(|value| {
+        serde_json::to_value(value).map_err(|e| TasksError::parse("response", e))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{AddArgs, ListArgs};
+    use crate::store::{init_store, Store};
+
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("tasks-daemon-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let socket = dir.join(SOCKET);
+        let backend = Backend::Local {
+            store:  Box::new(Store::new(dir.clone())),
+            cutoff: 0,
+        };
+        let server = {
+            let socket = socket.clone();
+            thread::spawn(move || serve(backend, &socket, None))
+        };
+        let mut client = None;
+        for _ in 0..100 {
+            client = Client::connect(&socket);
+            if client.is_some() {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let mut client = client.expect("daemon did not start");
+
+        let args = AddArgs {
+            description: None,
+            priority:    100,
+            name:        vec!["water".to_string(), "plants".to_string()],
+            tag:         vec!["home".to_string()],
+            estimate:    None,
+            energy:      None,
+            window:      None,
+            force:       false,
+        };
+        let added: crate::task::Task = client.call("add", &args).unwrap();
+        let listed: Vec<crate::task::Task> = client.call("list", ListArgs::default()).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name(), "water plants");
+        let reminder: Option<crate::task::Task> =
+            client.call("reminder", json!({ "interval": 60 })).unwrap();
+        assert_eq!(reminder.unwrap().id(), listed[0].id());
+        let rolled: Vec<crate::task::Task> = client.call("random", json!({})).unwrap();
+        assert_eq!(rolled.len(), 1);
+
+        let remote = Backend::Remote(std::cell::RefCell::new(Client::connect(&socket).unwrap()));
+        let found = remote.find(&["water".to_string()]).unwrap();
+        let last = remote.find(&["last".to_string()]).unwrap();
+        assert_eq!(last.id(), found.id());
+        drop(remote);
+
+        let missing = client.call::<Value>("touch", json!({ "id": "nope" }));
+        assert!(matches!(missing, Err(TasksError::NotFound(_))));
+        let unknown = client.call::<Value>("frobnicate", json!({}));
+        assert!(matches!(unknown, Err(TasksError::Parse { .. })));
+
+        let closed: crate::task::Task = client.call("close", json!({ "id": added.id() })).unwrap();
+        assert_eq!(closed.id(), added.id());
+        let tags: Vec<String> = client.call("tags", json!({})).unwrap();
+        assert!(tags.is_empty());
+
+        client.call::<Value>("shutdown", json!({})).unwrap();
+        server.join().unwrap().unwrap();
+        assert!(!socket.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
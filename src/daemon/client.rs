@@ -0,0 +1,94 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{Response, RpcError, TASKS_ERROR};
+use crate::error::TasksError;
+
+/// A connection to a running `tasks serve`
+pub struct Client {
+    socket: PathBuf,
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next:   u64,
+}
+
+impl Client {
+    /// Connects to the daemon on `socket`, or returns `None` if none is listening
+    pub fn connect(socket: &Path) -> Option<Client> {
+        let writer = UnixStream::connect(socket).ok()?;
+        let reader = BufReader::new(writer.try_clone().ok()?);
+        return Some(Client {
+            socket: socket.to_path_buf(),
+            reader,
+            writer,
+            next: 0,
+        });
+    }
+
+    pub fn call<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: impl Serialize,
+    ) -> Result<T, TasksError> {
+        self.next += 1;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.next,
+            "method": method,
+            "params": params,
+        });
+        let mut line = request.to_string();
+        line.push('\n');
+        self.writer
+            .write_all(line.as_bytes())
+            .map_err(TasksError::io(&self.socket))?;
+        let mut line = String::new();
+        let read = self
+            .reader
+            .read_line(&mut line)
+            .map_err(TasksError::io(&self.socket))?;
+        if read == 0 {
+            let error = io::Error::new(io::ErrorKind::UnexpectedEof, "the daemon hung up");
+            return Err(TasksError::io(&self.socket)(error));
+        }
+        let response: Response =
+            serde_json::from_str(&line).map_err(|e| TasksError::parse("daemon response", e))?;
+        if let Some(error) = response.error {
+            return Err(self.error(error));
+        }
+        let result = response.result.unwrap_or(Value::Null);
+        return serde_json::from_value(result).map_err(|e| TasksError::parse("daemon response", e));
+    }
+
+    // Rebuilds the error the daemon ran into, so that it is reported (and exits) the same way
+    fn error(&self, error: RpcError) -> TasksError {
+        let field = |name: &str| error.data[name].as_str().unwrap_or_default().to_string();
+        if error.code != TASKS_ERROR {
+            return TasksError::parse("daemon", error.message);
+        }
+        return match error.data["kind"].as_str().unwrap_or_default() {
+            "not_found" => TasksError::NotFound(error.message),
+            "ambiguous" => TasksError::Ambiguous {
+                matches: error.data["matches"].as_u64().unwrap_or_default() as usize,
+                reason:  field("reason"),
+            },
+            "parse" => TasksError::parse(field("what"), field("reason")),
+            "hook" => TasksError::Hook {
+                hook:    field("hook"),
+                message: field("reason"),
+            },
+            "duplicate" => TasksError::Duplicate {
+                name:    field("name"),
+                similar: serde_json::from_value(error.data["similar"].clone()).unwrap_or_default(),
+            },
+            "invalid_bank" => TasksError::InvalidBank(error.message),
+            "locked" => TasksError::Locked(PathBuf::from(field("path"))),
+            _ => TasksError::io(&self.socket)(io::Error::other(error.message)),
+        };
+    }
+}
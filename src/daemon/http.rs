@@ -0,0 +1,340 @@
+//! The REST API of `tasks serve --http`.
+//!
+//! | Route | |
+//! | ----- | - |
+//! | `GET /tasks/active` | Active tasks, filtered like `tasks list` by `q`, `tags`, `ntags`, `time`, `energy`, `sort`, `reverse`, `limit` and `offset` |
+//! | `POST /tasks/active` | Adds a task, from the fields of `tasks add` |
+//! | `GET /tasks/closed` | Closed tasks |
+//! | `GET /tasks/<id>` | One task, active or closed |
+//! | `PUT /tasks/<id>` | Replaces an active task, needs `If-Match` |
+//! | `POST /tasks/<id>/touch` | Marks a task as done for now |
+//! | `POST /tasks/<id>/close` | Closes a task |
+//! | `GET /random` | Picks tasks like `tasks random`, by `tags`, `ntags`, `time`, `energy`, `n` and `force` |
+//! | `POST /undo` | Reverts the latest change |
+//!
+//! Every task carries an `ETag` of its content. Changes to a task honour `If-Match`, and answer
+//! `412 Precondition Failed` when the task changed since it was read.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::{io, thread};
+
+use clap::ValueEnum;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use super::describe;
+use crate::backend::{Backend, RandomArgs};
+use crate::error::TasksError;
+use crate::parser::ListArgs;
+use crate::task::{Energy, Task};
+
+type Reply = Response<io::Cursor<Vec<u8>>>;
+
+/// Serves `backend` on `address` from a new thread, until the returned server is unblocked
+pub fn spawn(backend: Arc<Mutex<Backend>>, address: SocketAddr) -> Result<Arc<Server>, TasksError> {
+    let server = Server::http(address).map_err(|e| {
+        TasksError::io(Path::new(&address.to_string()))(io::Error::other(e.to_string()))
+    })?;
+    println!("Serving tasks on http://{}", address);
+    let server = Arc::new(server);
+    let incoming = server.clone();
+    thread::spawn(move || {
+        for mut request in incoming.incoming_requests() {
+            // Read before taking the lock, so that a slow client does not hold up the others
+            let mut content = String::new();
+            if let Err(error) = request.as_reader().read_to_string(&mut content) {
+                eprintln!("{}", error);
+                continue;
+            }
+            let reply = {
+                let backend = backend
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                route(&backend, &request, &content).and_then(|reply| backend.save().map(|_| reply))
+            };
+            let reply = reply.unwrap_or_else(|error| failure(&error));
+            if let Err(error) = request.respond(reply) {
+                eprintln!("{}", error);
+            }
+        }
+    });
+    return Ok(server);
+}
+
+fn route(backend: &Backend, request: &Request, content: &str) -> Result<Reply, TasksError> {
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), decode_query(query)),
+        None => (request.url().to_string(), vec![]),
+    };
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let method = request.method().clone();
+    return match (&method, segments.as_slice()) {
+        (Method::Get, ["tasks", "active"]) => tasks(&backend.list(&list_args(&query)?)?),
+        (Method::Post, ["tasks", "active"]) => {
+            let task = backend.add(&body(content)?)?;
+            Ok(with_etag(reply(201, &task), &task))
+        },
+        (Method::Get, ["tasks", "closed"]) => tasks(&backend.closed()?),
+        (Method::Get, ["random"]) => tasks(&backend.random(&random_args(&query)?)?),
+        (Method::Post, ["undo"]) => Ok(reply(200, &json!({ "undone": backend.undo()? }))),
+        (Method::Get, ["tasks", id]) => {
+            let task = backend.get(id)?;
+            Ok(with_etag(reply(200, &task), &task))
+        },
+        (Method::Put, ["tasks", id]) => {
+            let current = backend.get(id)?;
+            if if_match(request).is_none() {
+                return Ok(message(428, "Replacing a task needs an If-Match header"));
+            }
+            if let Some(stale) = precondition(request, &current) {
+                return Ok(stale);
+            }
+            let task: Task = body(content)?;
+            if task.id() != current.id() {
+                return Ok(message(400, "The id of a task cannot change"));
+            }
+            let task = backend.replace(&task)?;
+            Ok(with_etag(reply(200, &task), &task))
+        },
+        (Method::Post, ["tasks", id, action @ ("touch" | "close")]) => {
+            // Only active tasks can be touched or closed
+            let current = backend.pick(id)?;
+            if let Some(stale) = precondition(request, &current) {
+                return Ok(stale);
+            }
+            let task = match *action {
+                "touch" => backend.touch(&current)?,
+                _ => backend.close(&current)?,
+            };
+            Ok(with_etag(reply(200, &task), &task))
+        },
+        (_, ["tasks", "active" | "closed"] | ["random"] | ["undo"] | ["tasks", _, ..]) => {
+            Ok(message(405, "Method not allowed"))
+        },
+        _ => Ok(message(404, "No such route")),
+    };
+}
+
+/// The version of a task that `ETag` and `If-Match` refer to: a hash of its content
+pub fn etag(task: &Task) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(task)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    return format!("\"{:016x}\"", hasher.finish());
+}
+
+fn if_match(request: &Request) -> Option<String> {
+    return request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("If-Match"))
+        .map(|header| header.value.to_string());
+}
+
+// A `412` when the client's `If-Match` does not name the current version of `task`
+fn precondition(request: &Request, task: &Task) -> Option<Reply> {
+    let expected = if_match(request)?;
+    let current = etag(task);
+    let matches = expected
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == current);
+    if matches {
+        return None;
+    }
+    return Some(with_etag(
+        message(412, "The task changed since it was read"),
+        task,
+    ));
+}
+
+fn body<T: DeserializeOwned>(content: &str) -> Result<T, TasksError> {
+    return serde_json::from_str(content).map_err(|e| TasksError::parse("request body", e));
+}
+
+fn list_args(query: &[(String, String)]) -> Result<ListArgs, TasksError> {
+    let mut args = ListArgs::default();
+    for (key, value) in query {
+        match key.as_str() {
+            "q" => args
+                .terms
+                .extend(value.split_whitespace().map(String::from)),
+            "tags" => args.tags.get_or_insert_with(Vec::new).extend(split(value)),
+            "ntags" => args.ntags.get_or_insert_with(Vec::new).extend(split(value)),
+            "sort" => {
+                for key in split(value) {
+                    args.sort.push(key.parse()?);
+                }
+            },
+            "reverse" => args.reverse = flag(key, value)?,
+            "limit" => args.limit = Some(number(key, value)?),
+            "offset" => args.offset = number(key, value)?,
+            "time" => args.time = Some(value.parse()?),
+            "energy" => args.energy = Some(energy(value)?),
+            _ => {
+                return Err(TasksError::parse(
+                    "query",
+                    format!("unknown parameter `{}`", key),
+                ))
+            },
+        }
+    }
+    return Ok(args);
+}
+
+fn random_args(query: &[(String, String)]) -> Result<RandomArgs, TasksError> {
+    let mut args = RandomArgs::default();
+    for (key, value) in query {
+        match key.as_str() {
+            "tags" => args.tags.extend(split(value)),
+            "ntags" => args.ntags.extend(split(value)),
+            "n" => args.n = number(key, value)?,
+            "force" => args.force = flag(key, value)?,
+            "time" => args.time = Some(value.parse()?),
+            "energy" => args.energy = Some(energy(value)?),
+            _ => {
+                return Err(TasksError::parse(
+                    "query",
+                    format!("unknown parameter `{}`", key),
+                ))
+            },
+        }
+    }
+    return Ok(args);
+}
+
+fn split(value: &str) -> impl Iterator<Item = String> + '_ {
+    return value
+        .split(',')
+        .filter(|part| !part.is_empty())
+        .map(String::from);
+}
+
+fn number<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, TasksError> {
+    return value
+        .parse()
+        .map_err(|_| TasksError::parse("query", format!("`{}` should be a number", key)));
+}
+
+fn energy(value: &str) -> Result<Energy, TasksError> {
+    return Energy::from_str(value, true)
+        .map_err(|_| TasksError::parse("query", "`energy` should be low, medium or high"));
+}
+
+// `?force` alone means true
+fn flag(key: &str, value: &str) -> Result<bool, TasksError> {
+    return match value {
+        "" | "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(TasksError::parse(
+            "query",
+            format!("`{}` should be true or false", key),
+        )),
+    };
+}
+
+fn decode_query(query: &str) -> Vec<(String, String)> {
+    return query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect();
+}
+
+// Percent decoding, with `+` for spaces. Malformed escapes are kept as they are
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    return String::from_utf8_lossy(&decoded).into_owned();
+}
+
+fn tasks(tasks: &[Task]) -> Result<Reply, TasksError> {
+    return Ok(reply(200, &tasks));
+}
+
+fn reply(status: u16, value: &impl Serialize) -> Reply {
+    let content = serde_json::to_string(value).unwrap_or_default();
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    return Response::from_string(content)
+        .with_status_code(status)
+        .with_header(header);
+}
+
+fn with_etag(reply: Reply, task: &Task) -> Reply {
+    let header = Header::from_bytes(&b"ETag"[..], etag(task).as_bytes()).unwrap();
+    return reply.with_header(header);
+}
+
+fn message(status: u16, message: &str) -> Reply {
+    return reply(status, &json!({ "error": { "message": message } }));
+}
+
+fn failure(error: &TasksError) -> Reply {
+    let status = match error {
+        TasksError::NotFound(_) => 404,
+        TasksError::Parse { .. } => 400,
+        TasksError::Ambiguous { .. } | TasksError::Hook { .. } | TasksError::Duplicate { .. } => {
+            409
+        },
+        TasksError::Locked(_) => 423,
+        TasksError::InvalidBank(_) | TasksError::Io { .. } | TasksError::Terminal(_) => 500,
+    };
+    let mut details = describe(error);
+    details["message"] = Value::String(error.to_string());
+    return reply(status, &json!({ "error": details }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries() {
+        let query =
+            decode_query("q=water+pl%61nts&tags=home,garden&reverse&limit=2&sort=-priority");
+        assert_eq!(query[0], ("q".to_string(), "water plants".to_string()));
+        let args = list_args(&query).unwrap();
+        assert_eq!(args.terms, vec!["water", "plants"]);
+        assert_eq!(args.tags.unwrap(), vec!["home", "garden"]);
+        assert!(args.reverse);
+        assert_eq!(args.limit, Some(2));
+        assert!(list_args(&decode_query("limit=lots")).is_err());
+        assert!(random_args(&decode_query("colour=red")).is_err());
+        assert_eq!(random_args(&decode_query("n=3&force")).unwrap().n, 3);
+        let args = random_args(&decode_query("time=1h%2030m&energy=Low")).unwrap();
+        assert_eq!(args.time, Some("90m".parse().unwrap()));
+        assert_eq!(args.energy, Some(Energy::Low));
+        assert_eq!(decode("100%"), "100%");
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::Duration;
+
+use crate::error::TasksError;
+
+/// Parses an amount of time as people write it: `30m`, `2h`, `1h30m`, `1h 30m`, `3d` or `2w`
+pub fn parse(text: &str) -> Result<Duration, TasksError> {
+    let invalid = || {
+        TasksError::parse(
+            "duration",
+            format!(
+                "`{}` is not an amount of time like `30m`, `2h` or `1h30m`",
+                text
+            ),
+        )
+    };
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    let mut parts = 0;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let amount: i64 = digits.parse().map_err(|_| invalid())?;
+        let part = match c {
+            'm' => Duration::try_minutes(amount),
+            'h' => Duration::try_hours(amount),
+            'd' => Duration::try_days(amount),
+            'w' => Duration::try_weeks(amount),
+            _ => None,
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(invalid)?;
+        digits.clear();
+        parts += 1;
+    }
+    if parts == 0 || !digits.is_empty() {
+        return Err(invalid());
+    }
+    return Ok(total);
+}
+
+/// `1h 05m`, or `12m` under an hour
+pub fn format(duration: &Duration) -> String {
+    let minutes = duration.num_minutes();
+    if minutes < 60 {
+        return format!("{}m", minutes);
+    }
+    return format!("{}h {:02}m", minutes / 60, minutes % 60);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(parse("1h30m").unwrap(), Duration::minutes(90));
+        assert_eq!(parse("2w").unwrap(), Duration::days(14));
+        assert_eq!(format(&Duration::minutes(12)), "12m");
+        assert_eq!(format(&Duration::minutes(65)), "1h 05m");
+        assert_eq!(
+            parse(&format(&Duration::minutes(65))).unwrap(),
+            Duration::minutes(65)
+        );
+        for invalid in ["", "15", "m", "1h30", "3y", "1.5h"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
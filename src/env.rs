@@ -0,0 +1,115 @@
+use toml::value::Table;
+use toml::Value;
+
+use crate::error::TasksError;
+
+/// Prefix of the environment variables that override config keys
+pub const PREFIX: &str = "TASKS_";
+// Tables whose keys are written with hyphens, which variable names cannot hold
+const HYPHENATED: [&str; 1] = ["hooks"];
+
+/// Builds a config layer from `TASKS_*` variables, e.g. `TASKS_TASK_PATH` or `TASKS_CUTOFF`.
+/// Keys of a table are set with the table name as a prefix, so `TASKS_FORMATS_LIST` sets
+/// `list` in `[formats]`. In `[hooks]`, underscores stand for hyphens, so `TASKS_HOOKS_PRE_ADD`
+/// sets `pre-add`.
+/// Values are converted to the type of the key in `defaults`, and variables matching no key are
+/// ignored. Also returns the variable that set each (dotted) key
+pub fn layer(
+    defaults: &Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<(Table, Vec<(String, String)>), TasksError> {
+    let mut layer = Table::new();
+    let mut sources = vec![];
+    for (var, raw) in vars {
+        let Some(name) = var.strip_prefix(PREFIX) else {
+            continue;
+        };
+        for (key, default) in defaults {
+            let upper = key.to_uppercase();
+            if name == upper {
+                layer.insert(key.clone(), convert(&var, &raw, default)?);
+                sources.push((key.clone(), var.clone()));
+                break;
+            }
+            let Value::Table(table) = default else {
+                continue;
+            };
+            if let Some(inner) = name.strip_prefix(&format!("{}_", upper)) {
+                let mut inner = inner.to_lowercase();
+                if HYPHENATED.contains(&key.as_str()) {
+                    inner = inner.replace('_', "-");
+                }
+                let value = match table.get(&inner) {
+                    Some(default) => convert(&var, &raw, default)?,
+                    None => Value::String(raw.clone()),
+                };
+                let entry = layer
+                    .entry(key.clone())
+                    .or_insert_with(|| Value::Table(Table::new()));
+                if let Value::Table(entry) = entry {
+                    entry.insert(inner.clone(), value);
+                }
+                sources.push((format!("{}.{}", key, inner), var.clone()));
+                break;
+            }
+        }
+    }
+    return Ok((layer, sources));
+}
+
+/// Parses `raw` as the same type as `default`. `var` names the value in errors
+pub(crate) fn convert(var: &str, raw: &str, default: &Value) -> Result<Value, TasksError> {
+    let value = match default {
+        Value::Integer(_) => raw
+            .trim()
+            .parse()
+            .map(Value::Integer)
+            .map_err(|e| TasksError::parse(var, e))?,
+        Value::Boolean(_) => match raw.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Value::Boolean(true),
+            "0" | "false" | "no" | "off" | "" => Value::Boolean(false),
+            _ => return Err(TasksError::parse(var, "expected true or false")),
+        },
+        Value::Table(_) => {
+            return Err(TasksError::parse(
+                var,
+                format!("is a table, set its keys with {}_<KEY>", var),
+            ))
+        },
+        _ => Value::String(raw.to_string()),
+    };
+    return Ok(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_and_tables() {
+        let defaults: Table = toml::from_str(
+            "task_path = '~/.tasks'\ncutoff = 1\ndebug = false\n[formats]\n[hooks]\n",
+        )
+        .unwrap();
+        let vars = [
+            ("TASKS_CUTOFF", "60"),
+            ("TASKS_DEBUG", "1"),
+            ("TASKS_FORMATS_LIST", "compact"),
+            ("TASKS_HOOKS_PRE_ADD", "/bin/check"),
+            ("TASKS_UNKNOWN", "x"),
+            ("HOME", "/root"),
+        ]
+        .map(|(var, value)| (var.to_string(), value.to_string()));
+        let (layer, sources) = layer(&defaults, vars.into_iter()).unwrap();
+        let expected: Table =
+            toml::from_str(
+            "cutoff = 60\ndebug = true\n[formats]\nlist = 'compact'\n[hooks]\npre-add = '/bin/check'\n",
+        )
+        .unwrap();
+        assert_eq!(layer, expected);
+        assert_eq!(sources.len(), 4);
+
+        let vars = [("TASKS_CUTOFF".to_string(), "soon".to_string())];
+        assert!(super::layer(&defaults, vars.into_iter()).is_err());
+    }
+}
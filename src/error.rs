@@ -0,0 +1,87 @@
+use core::fmt;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Every error the `tasks` library can return
+#[derive(Debug)]
+pub enum TasksError {
+    /// Reading or writing `path` failed
+    Io { path: PathBuf, error: io::Error },
+    /// `what` (a store file, the config, ...) could not be parsed or serialized
+    Parse { what: String, message: String },
+    /// Nothing matched, or nothing was selected
+    NotFound(String),
+    /// Several tasks matched and one could not be picked interactively
+    Ambiguous { matches: usize, reason: String },
+    /// A bank name other than `active` or `closed`
+    InvalidBank(String),
+    /// Another process is holding the store lock
+    Locked(PathBuf),
+    /// Drawing to, or reading from, the terminal failed
+    Terminal(io::Error),
+    /// A hook refused a change, or could not be run
+    Hook { hook: String, message: String },
+    /// A new task looks like a copy of the `similar` active tasks
+    Duplicate {
+        name:    String,
+        similar: Vec<String>,
+    },
+}
+
+impl TasksError {
+    pub fn io(path: &Path) -> impl FnOnce(io::Error) -> TasksError + '_ {
+        return move |error| TasksError::Io {
+            path: path.to_path_buf(),
+            error,
+        };
+    }
+
+    pub fn parse(what: impl fmt::Display, message: impl fmt::Display) -> TasksError {
+        return TasksError::Parse {
+            what:    what.to_string(),
+            message: message.to_string(),
+        };
+    }
+}
+
+impl fmt::Display for TasksError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TasksError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            TasksError::Parse { what, message } => write!(f, "{}: {}", what, message),
+            TasksError::NotFound(what) => write!(f, "{}", what),
+            TasksError::Ambiguous { matches, reason } => {
+                write!(
+                    f,
+                    "{} tasks match, and none could be chosen: {}",
+                    matches, reason
+                )
+            },
+            TasksError::InvalidBank(bank) => write!(f, "Invalid bank name: {}", bank),
+            TasksError::Locked(path) => write!(
+                f,
+                "{} is locked by another `tasks` process, try again shortly",
+                path.display()
+            ),
+            TasksError::Terminal(error) => write!(f, "terminal: {}", error),
+            TasksError::Hook { hook, message } => write!(f, "{} hook {}", hook, message),
+            TasksError::Duplicate { name, similar } => write!(
+                f,
+                "`{}` looks like a duplicate of `{}`, add it anyway with --force",
+                name,
+                similar.join("`, `")
+            ),
+        }
+    }
+}
+
+impl Error for TasksError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TasksError::Io { error, .. } => Some(error),
+            TasksError::Terminal(error) => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Local};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal;
+
+use crate::error::TasksError;
+use crate::task::{Outcome, Task};
+
+// Restores the terminal however the session ends
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<RawMode, TasksError> {
+        check_terminal()?;
+        terminal::enable_raw_mode().map_err(TasksError::Terminal)?;
+        return Ok(RawMode);
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Fails unless the session can be run interactively
+pub fn check_terminal() -> Result<(), TasksError> {
+    if !io::stdin().is_terminal() {
+        return Err(TasksError::Terminal(io::Error::other(
+            "focus sessions need a terminal",
+        )));
+    }
+    return Ok(());
+}
+
+/// Counts down `length` in the terminal while `task` is worked on. `q`, escape or enter end the
+/// session early. Returns when the session ended, or `None` if it was abandoned with ctrl-c
+pub fn countdown(task: &Task, length: Duration) -> Result<Option<DateTime<Local>>, TasksError> {
+    let raw = RawMode::enable()?;
+    let end = Local::now() + length;
+    let mut out = io::stdout();
+    let ended = loop {
+        let now = Local::now();
+        let left = end.signed_duration_since(now);
+        if left <= Duration::zero() {
+            // Rings the bell
+            write!(out, "\x07").map_err(TasksError::Terminal)?;
+            break Some(now);
+        }
+        let seconds = left.num_seconds() + 1;
+        write!(
+            out,
+            "\r\x1b[2K{:02}:{:02} left on `{}` (q to end early)",
+            seconds / 60,
+            seconds % 60,
+            task.name()
+        )
+        .map_err(TasksError::Terminal)?;
+        out.flush().map_err(TasksError::Terminal)?;
+        let wait = left
+            .to_std()
+            .unwrap_or_default()
+            .min(StdDuration::from_secs(1));
+        if !event::poll(wait).map_err(TasksError::Terminal)? {
+            continue;
+        }
+        match event::read().map_err(TasksError::Terminal)? {
+            Event::Key(key) if is_interrupt(&key) => break None,
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter,
+                kind: KeyEventKind::Press,
+                ..
+            }) => break Some(Local::now()),
+            _ => {},
+        }
+    };
+    drop(raw);
+    println!();
+    return Ok(ended);
+}
+
+/// Asks how the session went. Snoozing snoozes the task until `snooze_until`
+pub fn ask(snooze_until: DateTime<Local>) -> Result<Outcome, TasksError> {
+    print!("[d]one, [c]lose, [s]nooze or s[k]ip? ");
+    io::stdout().flush().map_err(TasksError::Terminal)?;
+    let raw = RawMode::enable()?;
+    let outcome = loop {
+        let Event::Key(key) = event::read().map_err(TasksError::Terminal)? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        if is_interrupt(&key) {
+            break Outcome::Skipped;
+        }
+        match key.code {
+            KeyCode::Char('d') => break Outcome::Done,
+            KeyCode::Char('c') => break Outcome::Closed,
+            KeyCode::Char('s') => {
+                break Outcome::Snoozed {
+                    until: snooze_until,
+                }
+            },
+            KeyCode::Char('k') | KeyCode::Esc => break Outcome::Skipped,
+            _ => {},
+        }
+    };
+    drop(raw);
+    println!();
+    return Ok(outcome);
+}
+
+fn is_interrupt(key: &KeyEvent) -> bool {
+    return key.kind == KeyEventKind::Press
+        && key.code == KeyCode::Char('c')
+        && key.modifiers.contains(KeyModifiers::CONTROL);
+}
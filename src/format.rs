@@ -0,0 +1,433 @@
+use std::str::Chars;
+
+use colored::{ColoredString, Colorize};
+
+use crate::duration;
+use crate::error::TasksError;
+use crate::task::Task;
+
+/// Named templates, usable anywhere a template is
+pub const PRESETS: [(&str, &str); 4] = [
+    (
+        "full",
+        "Id: {id}\\n{name} - {type}\\n{description|No description provided.}\\nLast Performed: \
+         {age} ago\\nPriority: {priority}[\\nTags: {tags}][\\nEstimate: {estimate}][\\nEnergy: \
+         {energy}][\\nWindow: {window}]",
+    ),
+    ("oneline", "{id} {name}[ \\[{tags}\\]] ({age_short} ago)"),
+    ("compact", "{name} ({age_short} ago)[ \\[{tags}\\]]"),
+    (
+        "detail",
+        "Id: {id}\\nName: {name}\\nType: {type}\\nDescription: {description|none}\\nPriority: \
+         {priority}\\nTags: {tags|none}\\nCreated: {created}\\nLast performed: {last} ({age} \
+         ago)[\\nClosed: {closed}][\\nSnoozed until: {snoozed}][\\nEstimate: \
+         {estimate}][\\nEnergy: {energy}][\\nWindow: {window}]\\nWeight: {weight}[\\nChance: \
+         {chance}][\\nNotes:\\n{notes}][\\nHistory:\\n{history}]",
+    ),
+];
+
+const FIELDS: &str = "id, name, type, description, priority, tags, age, age_short, created, \
+                      last, estimate, energy, window, closed, snoozed, notes, history, weight, \
+                      chance";
+
+/// A parsed output template.
+///
+/// `{field}` is replaced by a field of the task, and `{field|text}` falls back to `text` when the
+/// field is empty. Anything between `[` and `]` is only printed when every field inside it is
+/// non-empty. `\n`, `\t`, `\\`, `\{`, `\}`, `\[` and `\]` are escapes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field {
+        field:   Field,
+        default: Option<String>,
+    },
+    Optional(Vec<Part>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Id,
+    Name,
+    Type,
+    Description,
+    Priority,
+    Tags,
+    Age,
+    AgeShort,
+    Created,
+    Last,
+    Estimate,
+    Energy,
+    Window,
+    Closed,
+    Snoozed,
+    Notes,
+    History,
+    Weight,
+    Chance,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        let field = match name {
+            "id" => Field::Id,
+            "name" => Field::Name,
+            "type" => Field::Type,
+            "description" => Field::Description,
+            "priority" => Field::Priority,
+            "tags" => Field::Tags,
+            "age" => Field::Age,
+            "age_short" => Field::AgeShort,
+            "created" => Field::Created,
+            "last" => Field::Last,
+            "estimate" => Field::Estimate,
+            "energy" => Field::Energy,
+            "window" => Field::Window,
+            "closed" => Field::Closed,
+            "snoozed" => Field::Snoozed,
+            "notes" => Field::Notes,
+            "history" => Field::History,
+            "weight" => Field::Weight,
+            "chance" => Field::Chance,
+            _ => return None,
+        };
+        return Some(field);
+    }
+
+    // `{chance}` is only known to commands that look at every task
+    fn value(&self, task: &Task, chance: Option<f64>) -> String {
+        let now = chrono::Local::now();
+        let hours = now.signed_duration_since(task.last_touched()).num_hours();
+        match self {
+            Field::Id => task.id().to_string(),
+            Field::Name => task.name().to_string(),
+            Field::Type => task.task_type().to_string(),
+            Field::Description => task
+                .description()
+                .as_deref()
+                .unwrap_or_default()
+                .split("\\n")
+                .map(|line| line.trim())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Field::Priority => task.priority().to_string(),
+            Field::Tags => task.tags().join(", "),
+            Field::Age if hours > 24 => {
+                let days = hours / 24;
+                let unit = if days > 1 { "days" } else { "day" };
+                format!("{} {}, {} hours", days, unit, hours % 24)
+            },
+            Field::Age => format!("{} hours", hours),
+            Field::AgeShort if hours >= 48 => format!("{}d", hours / 24),
+            Field::AgeShort => format!("{}h", hours),
+            Field::Created => task.created().format("%Y-%m-%d").to_string(),
+            Field::Last => task.last_touched().format("%Y-%m-%d %H:%M").to_string(),
+            Field::Estimate => task
+                .estimate()
+                .map(|estimate| estimate.to_string())
+                .unwrap_or_default(),
+            Field::Energy => task
+                .energy()
+                .map(|energy| energy.to_string())
+                .unwrap_or_default(),
+            Field::Window => task
+                .window()
+                .map(|window| window.to_string())
+                .unwrap_or_default(),
+            Field::Closed => task
+                .closed()
+                .map(|closed| closed.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default(),
+            Field::Snoozed => task
+                .snoozed_until()
+                .filter(|_| task.is_snoozed(&now))
+                .map(|until| until.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default(),
+            Field::Notes => task
+                .notes()
+                .iter()
+                .map(|note| format!("  {} {}", note.at.format("%Y-%m-%d %H:%M"), note.text))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Field::History => task
+                .intervals()
+                .iter()
+                .map(|interval| {
+                    let spent = duration::format(&interval.duration_since(None));
+                    let outcome = match &interval.focus {
+                        Some(outcome) => format!(", focus {}", outcome),
+                        None => String::new(),
+                    };
+                    format!(
+                        "  {} - {} ({}{})",
+                        interval.start.format("%Y-%m-%d %H:%M"),
+                        interval.end.format("%H:%M"),
+                        spent,
+                        outcome
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Field::Weight => task.weight(&now).to_string(),
+            Field::Chance => chance
+                .map(|chance| format!("{:.1}%", chance * 100.0))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn style(&self, value: &str) -> ColoredString {
+        match self {
+            Field::Id => value.cyan(),
+            Field::Name => value.bold().yellow(),
+            Field::Type => value.green(),
+            Field::Description => value.italic().blue(),
+            Field::Priority => value.blue(),
+            Field::Tags => value.yellow(),
+            Field::Age | Field::AgeShort => value.magenta(),
+            Field::Created | Field::Last => value.normal(),
+            Field::Estimate => value.cyan(),
+            Field::Energy => value.green(),
+            Field::Window => value.green(),
+            Field::Closed | Field::Snoozed => value.red(),
+            Field::Notes => value.italic(),
+            Field::History => value.normal(),
+            Field::Weight | Field::Chance => value.blue(),
+        }
+    }
+}
+
+impl Template {
+    /// Parses `source`, which is either the name of a preset or a template
+    pub fn parse(source: &str) -> Result<Template, TasksError> {
+        let source = PRESETS
+            .iter()
+            .find(|(name, _)| *name == source)
+            .map_or(source, |(_, template)| template);
+        let mut chars = source.chars();
+        let parts = parse_parts(&mut chars, false)?;
+        return Ok(Template { parts });
+    }
+
+    pub fn full() -> Template {
+        return Template::parse("full").expect("the full preset is valid");
+    }
+
+    /// Whether a rendered task spans several lines, in which case tasks are separated by a blank
+    /// line
+    pub fn is_multiline(&self) -> bool {
+        return has_newline(&self.parts);
+    }
+
+    pub fn render(&self, task: &Task) -> String {
+        return render_parts(&self.parts, task, None).0;
+    }
+
+    /// Renders `task` with how likely random selection is to pick it, for `{chance}`
+    pub fn render_with_chance(&self, task: &Task, chance: Option<f64>) -> String {
+        return render_parts(&self.parts, task, chance).0;
+    }
+}
+
+fn parse_error(message: impl std::fmt::Display) -> TasksError {
+    return TasksError::parse("format", message);
+}
+
+fn parse_parts(chars: &mut Chars, optional: bool) -> Result<Vec<Part>, TasksError> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some(c @ ('\\' | '{' | '}' | '[' | ']')) => text.push(c),
+                Some(c) => return Err(parse_error(format!("unknown escape `\\{}`", c))),
+                None => return Err(parse_error("template ends with `\\`")),
+            },
+            '{' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err(parse_error("unclosed `{`")),
+                    }
+                }
+                let (name, default) = match inner.split_once('|') {
+                    Some((name, default)) => (name, Some(default.to_string())),
+                    None => (inner.as_str(), None),
+                };
+                if name.trim() == "due" {
+                    return Err(parse_error(
+                        "tasks have no due date, so there is no `{due}`",
+                    ));
+                }
+                let field = Field::from_name(name.trim()).ok_or_else(|| {
+                    parse_error(format!(
+                        "unknown field `{}`, expected one of {}",
+                        name, FIELDS
+                    ))
+                })?;
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                parts.push(Part::Field { field, default });
+            },
+            '[' => {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                parts.push(Part::Optional(parse_parts(chars, true)?));
+            },
+            ']' if optional => {
+                parts.push(Part::Text(text));
+                return Ok(parts);
+            },
+            '}' | ']' => return Err(parse_error(format!("unmatched `{}`", c))),
+            c => text.push(c),
+        }
+    }
+    if optional {
+        return Err(parse_error("unclosed `[`"));
+    }
+    parts.push(Part::Text(text));
+    return Ok(parts);
+}
+
+fn has_newline(parts: &[Part]) -> bool {
+    return parts.iter().any(|part| match part {
+        Part::Text(text) => text.contains('\n'),
+        Part::Field { field, .. } => *field == Field::Description,
+        Part::Optional(parts) => has_newline(parts),
+    });
+}
+
+// Also returns whether every field was non-empty, which decides if an optional section is shown
+fn render_parts(parts: &[Part], task: &Task, chance: Option<f64>) -> (String, bool) {
+    let mut out = String::new();
+    let mut complete = true;
+    for part in parts {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Field { field, default } => {
+                let mut value = field.value(task, chance);
+                if value.is_empty() {
+                    complete = false;
+                    value = default.clone().unwrap_or_default();
+                }
+                // Styled line by line, so that colors do not bleed into indentation or the next
+                // line
+                let lines: Vec<String> = value
+                    .split('\n')
+                    .map(|line| field.style(line).to_string())
+                    .collect();
+                out.push_str(&lines.join("\n"));
+            },
+            Part::Optional(parts) => {
+                let (text, complete) = render_parts(parts, task, chance);
+                if complete {
+                    out.push_str(&text);
+                }
+            },
+        }
+    }
+    return (out, complete);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(tags: Vec<String>) -> Task {
+        return Task::new_todo("water plants".to_string(), None, Some(3), Some(tags));
+    }
+
+    // Strips the colour codes, which depend on the terminal the tests run in
+    fn plain(text: String) -> String {
+        let mut out = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    chars.find(|c| c.is_ascii_alphabetic());
+                },
+                c => out.push(c),
+            }
+        }
+        return out;
+    }
+
+    fn render(template: &Template, task: &Task) -> String {
+        return plain(template.render(task));
+    }
+
+    #[test]
+    fn optional_sections_need_every_field() {
+        let template = Template::parse("{name}[ ({tags})]").unwrap();
+        assert_eq!(render(&template, &task(vec![])), "water plants");
+        assert_eq!(
+            render(
+                &template,
+                &task(vec!["home".to_string(), "garden".to_string()])
+            ),
+            "water plants (home, garden)"
+        );
+    }
+
+    #[test]
+    fn defaults_and_escapes() {
+        let template = Template::parse("\\{{description|none}\\}\\t{priority}").unwrap();
+        assert_eq!(render(&template, &task(vec![])), "{none}\t3");
+    }
+
+    #[test]
+    fn presets() {
+        let task = task(vec!["home".to_string()]);
+        assert_eq!(
+            render(&Template::parse("compact").unwrap(), &task),
+            "water plants (0h ago) [home]"
+        );
+        assert!(Template::full().is_multiline());
+        assert!(!Template::parse("oneline").unwrap().is_multiline());
+    }
+
+    #[test]
+    fn details() {
+        let task = task(vec![]);
+        let template = Template::parse("{weight}[ {chance}][ closed {closed}]").unwrap();
+        assert_eq!(render(&template, &task), "0");
+        assert_eq!(
+            plain(template.render_with_chance(&task, Some(0.25))),
+            "0 25.0%"
+        );
+        assert!(render(&template, &task.completed()).starts_with("0 closed 20"));
+
+        let now = chrono::Local::now();
+        let worked = task.tracked(now - chrono::Duration::minutes(90), now);
+        let history = render(&Template::parse("{history}").unwrap(), &worked);
+        assert!(history.ends_with(" (1h 30m)"), "{}", history);
+        let notes = Template::parse("{notes|no notes}").unwrap();
+        assert_eq!(render(&notes, &task), "no notes");
+        assert!(render(&notes, &task.noted("halfway")).ends_with(" halfway"));
+        assert!(Template::parse("detail").unwrap().is_multiline());
+    }
+
+    #[test]
+    fn merged_descriptions() {
+        let description = |text| Task::new_todo("water plants".to_string(), text, None, None);
+        let merged = description(Some("ferns\\nby the door")).merged(&description(Some("cactus")));
+        assert_eq!(
+            render(&Template::parse("{description}").unwrap(), &merged),
+            "ferns\nby the door\ncactus"
+        );
+    }
+
+    #[test]
+    fn invalid_templates() {
+        for source in ["{nope}", "{due}", "{name", "[{name}", "{name}]", "\\x"] {
+            assert!(Template::parse(source).is_err(), "{}", source);
+        }
+    }
+}
@@ -1,7 +1,20 @@
+pub mod backend;
+pub mod completions;
 pub mod config;
+pub mod daemon;
+pub mod duration;
 pub mod env;
+pub mod error;
+pub mod focus;
+pub mod format;
 pub mod parser;
+pub mod report;
+pub mod sample;
+pub mod sort;
 pub mod store;
 pub mod task;
+pub mod tui;
+pub mod window;
 
 pub use config::Config;
+pub use error::TasksError;
@@ -2,18 +2,49 @@
 // change config so that cutoff accepts strings
 // implement undo file
 // implement proj files
-use std::error::Error;
-use std::process::exit;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::process::{self, exit};
+use std::{env, fs};
 
+use clap::error::{ContextKind, ContextValue};
 use clap::Parser;
-use tasks::parser::{AddArgs, Cli, Commands, EditArgs, ListArgs};
-use tasks::store::{init_store, Store};
-use tasks::task::Task;
-use tasks::Config;
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
-    let mut conf = Config::from_path();
+use tasks::backend::{Backend, RandomArgs};
+use tasks::completions::{write_completions, CompletionKind};
+use tasks::daemon::{self, SOCKET};
+use tasks::format::Template;
+use tasks::parser::{AddArgs, Cli, Commands, ConfigCommand, EditArgs, ListArgs};
+use tasks::report::{parse_since, TimeReport};
+use tasks::store::{fsck, init_store, restore, snapshots, Store};
+use tasks::task::{Interval, Task};
+use tasks::{config, duration, focus, tui, Config, TasksError};
+use toml::Value;
+
+// Exit codes, documented in the README.
+// 2 is left to clap, which uses it for usage errors.
+const EXIT_NOT_FOUND: i32 = 1;
+const EXIT_AMBIGUOUS: i32 = 3;
+const EXIT_PARSE: i32 = 4;
+const EXIT_IO: i32 = 5;
+const EXIT_INVALID_BANK: i32 = 6;
+const EXIT_LOCKED: i32 = 7;
+const EXIT_FSCK_ISSUES: i32 = 8;
+const EXIT_HOOK: i32 = 9;
+const EXIT_DUPLICATE: i32 = 10;
+
+fn main() {
+    let cli = parse_cli();
+    // Runs without loading the config, so that a broken one can still be fixed
+    if let Some(Commands::Config { command }) = &cli.command {
+        if let Err(error) = run_config(cli.config.as_deref(), command) {
+            fail(error);
+        }
+        return;
+    }
+    let mut conf = match Config::load(cli.config.as_deref()) {
+        Ok(conf) => conf,
+        Err(error) => fail(error),
+    };
     if !conf.debug && cli.debug {
         conf.debug = cli.debug;
     }
@@ -21,210 +52,642 @@ fn main() -> Result<(), Box<dyn Error>> {
         dbg!(&cli.command);
         println!(
             "The path where tasks are stored: {}",
-            &conf.task_path.to_str().ok_or("")?
+            conf.task_path.display()
         );
         dbg!(&conf);
     }
+    // NO_COLOR is honoured by `colored` itself
+    if !std::io::stdout().is_terminal() && std::env::var_os("CLICOLOR_FORCE").is_none() {
+        colored::control::set_override(false);
+    }
+    if let Err(error) = run(cli, conf) {
+        fail(error);
+    }
+}
+
+// An unknown subcommand may be an alias from the config, which is expanded in place
+fn parse_cli() -> Cli {
+    let args: Vec<String> = env::args().collect();
+    let error = match Cli::try_parse_from(&args) {
+        Ok(cli) => return cli,
+        Err(error) => error,
+    };
+    if let Some(ContextValue::String(name)) = error.get(ContextKind::InvalidSubcommand) {
+        if let Some(args) = expand_alias(&args, name) {
+            return Cli::parse_from(args);
+        }
+    }
+    error.exit();
+}
+
+fn expand_alias(args: &[String], name: &str) -> Option<Vec<String>> {
+    let flag = args
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|i| args.get(i + 1).map(String::as_str))
+        .or_else(|| args.iter().find_map(|arg| arg.strip_prefix("--config=")));
+    let conf = Config::load(flag.map(Path::new)).ok()?;
+    let expansion = conf.aliases.get(name)?;
+    let position = args.iter().position(|arg| arg == name)?;
+    let mut expanded = args[..position].to_vec();
+    expanded.extend(expansion.split_whitespace().map(String::from));
+    expanded.extend_from_slice(&args[position + 1..]);
+    return Some(expanded);
+}
+
+fn fail(error: TasksError) -> ! {
+    eprintln!("{}", error);
+    exit(exit_code(&error));
+}
+
+fn exit_code(error: &TasksError) -> i32 {
+    match error {
+        TasksError::NotFound(_) => EXIT_NOT_FOUND,
+        TasksError::Ambiguous { .. } => EXIT_AMBIGUOUS,
+        TasksError::Parse { .. } => EXIT_PARSE,
+        TasksError::Io { .. } | TasksError::Terminal(_) => EXIT_IO,
+        TasksError::InvalidBank(_) => EXIT_INVALID_BANK,
+        TasksError::Locked(_) => EXIT_LOCKED,
+        TasksError::Hook { .. } => EXIT_HOOK,
+        TasksError::Duplicate { .. } => EXIT_DUPLICATE,
+    }
+}
+
+fn run(cli: Cli, mut conf: Config) -> Result<(), TasksError> {
     if cli.force || cli.ntags.is_some() || cli.tags.is_some() {
+        let template = template(&conf, cli.format.as_deref(), "random")?;
         conf.cutoff = 0;
-        run_random(&conf, 1, cli.tags, cli.ntags);
-        exit(0);
+        init_store(&conf.task_path)?;
+        let args = RandomArgs {
+            tags: cli.tags.unwrap_or_default(),
+            ntags: cli.ntags.unwrap_or_default(),
+            force: true,
+            n: 1,
+            ..Default::default()
+        };
+        return run_random(&conf, &template, &args);
     }
     let command = cli.command.unwrap_or(Commands::Random {
-        n:     1,
-        tags:  None,
-        ntags: None,
-        force: false,
+        n:      1,
+        tags:   None,
+        ntags:  None,
+        force:  false,
+        time:   None,
+        energy: None,
     });
     if conf.debug {
         dbg!(&command);
     };
+    let name = match &command {
+        Commands::List(_) | Commands::View { .. } => "list",
+        Commands::Last => "last",
+        Commands::Show { .. } => "show",
+        Commands::Merge { .. } => "merge",
+        Commands::Note { .. } => "note",
+        Commands::Random { .. } => "random",
+        Commands::Hook { .. } => "hook",
+        Commands::Add(_) => "add",
+        Commands::Done { .. } => "done",
+        Commands::Close { .. } => "close",
+        Commands::Edit(_) => "edit",
+        Commands::Focus { .. } => "focus",
+        _ => "default",
+    };
+    let template = template(&conf, cli.format.as_deref(), name)?;
     init_store(&conf.task_path)?;
     match &command {
-        Commands::List(args) => run_list(&conf, args),
+        Commands::List(args) => run_list(&conf, &template, args),
         Commands::Last => run_list(
             &conf,
+            &template,
             &ListArgs {
-                tags:  None,
-                ntags: None,
                 terms: ["last".into()].to_vec(),
+                ..Default::default()
             },
         ),
-        Commands::Add(opts) => run_add(&conf, opts),
-        Commands::Done { terms } => run_touch(&conf, terms),
-        Commands::Close { terms } => run_complete(&conf, terms),
+        Commands::Show { terms } => run_show(&conf, &template, terms),
+        Commands::Merge { keep, duplicate } => run_merge(&conf, &template, keep, duplicate),
+        Commands::Note { terms, text } => run_note(&conf, &template, terms, text),
+        Commands::Add(opts) => run_add(&conf, &template, opts),
+        Commands::Done { terms } => run_touch(&conf, &template, terms),
+        Commands::Close { terms } => run_complete(&conf, &template, terms),
         Commands::Random {
             tags,
             ntags,
             n,
             force,
+            time,
+            energy,
+        } => {
+            let args = RandomArgs {
+                terms:  vec![],
+                tags:   tags.clone().unwrap_or_default(),
+                ntags:  ntags.clone().unwrap_or_default(),
+                n:      *n,
+                force:  *force,
+                time:   *time,
+                energy: *energy,
+            };
+            run_random(&conf, &template, &args)
+        },
+        Commands::Edit(args) => run_edit(&conf, &template, args),
+        Commands::Undo => run_undo(&conf),
+        Commands::Hook { tags, ntags } => {
+            run_hook(&conf, &template, tags.clone(), ntags.clone());
+            Ok(())
+        },
+        Commands::Completions { shell } => {
+            write_completions(*shell, &mut std::io::stdout()).map_err(TasksError::Terminal)
+        },
+        Commands::Complete { kind } => run_complete_values(&conf, *kind),
+        Commands::Tui => tui::run(&conf, &open_backend(&conf)),
+        Commands::Restore { list, snapshot } => run_restore(&conf, *list, snapshot.as_deref()),
+        Commands::Fsck { repair } => run_fsck(&conf, *repair),
+        Commands::Focus {
+            minutes,
+            tags,
+            ntags,
+            force,
         } => {
             if *force {
                 conf.cutoff = 0;
             }
-            run_random(&conf, *n, tags.clone(), ntags.clone());
+            run_focus(&conf, &template, *minutes, tags.clone(), ntags.clone())
         },
-        Commands::Edit(args) => run_edit(&conf, args),
-        Commands::Undo => run_undo(&conf),
-    };
-    Ok(())
+        Commands::Start { terms } => run_start(&conf, terms),
+        Commands::Stop => run_stop(&conf),
+        Commands::Report { time, since } => run_report(&conf, *time, since.as_deref()),
+        Commands::Serve { socket, http } => {
+            let socket = socket.clone().unwrap_or(conf.task_path.join(SOCKET));
+            let backend = Backend::Local {
+                store:  Box::new(open_store(&conf)),
+                cutoff: conf.cutoff,
+            };
+            daemon::serve(backend, &socket, *http)
+        },
+        Commands::View { name: Some(name) } => {
+            let view = conf
+                .views
+                .get(name)
+                .ok_or_else(|| TasksError::NotFound(format!("No view named `{}`", name)))?;
+            let template = match (&cli.format, &view.format) {
+                (None, Some(format)) => Template::parse(format)?,
+                _ => template,
+            };
+            run_list(&conf, &template, &view.list_args()?)
+        },
+        Commands::View { name: None } => {
+            if conf.views.is_empty() {
+                println!("No views are defined, add them to the [views] table of the config.");
+            }
+            for name in conf.views.keys() {
+                println!("{}", name);
+            }
+            Ok(())
+        },
+        Commands::Config { .. } => unreachable!("handled before the config is loaded"),
+    }
 }
 
-fn run_undo(conf: &Config) {
-    let store = Store::new(conf.task_path.clone());
-    match store.undo() {
-        Ok(item) => println!("Undone operation:\n{}", item),
-        Err(error) => println!("Error in undo: {}", error.to_string()),
-    }
+// `--format` wins over the `formats` table in the config
+fn template(conf: &Config, format: Option<&str>, command: &str) -> Result<Template, TasksError> {
+    return Template::parse(format.unwrap_or(conf.format(command)));
 }
 
-fn run_random(conf: &Config, n: u8, tags: Option<Vec<String>>, ntags: Option<Vec<String>>) {
-    let store = Store::new(conf.task_path.clone());
-    let items = store.filter_active(
-        &vec![],
+fn open_store(conf: &Config) -> Store {
+    return Store::new(conf.task_path.clone())
+        .with_backups(conf.backups())
+        .with_hooks(conf.hooks())
+        .with_windows(conf.windows.clone())
+        .with_repeats(conf.repeats());
+}
+
+// Goes through `tasks serve` when it is running
+fn open_backend(conf: &Config) -> Backend {
+    return Backend::open(open_store(conf), conf.cutoff, &conf.task_path.join(SOCKET));
+}
+
+fn run_undo(conf: &Config) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let item = backend.undo()?;
+    backend.save()?;
+    println!("Undone operation:\n{}", item);
+    return Ok(());
+}
+
+// Errors are swallowed, a broken store should not break every new shell
+fn run_hook(
+    conf: &Config,
+    template: &Template,
+    tags: Option<Vec<String>>,
+    ntags: Option<Vec<String>>,
+) {
+    let store = open_store(conf).without_lock_wait();
+    let backend = Backend::open(store, conf.cutoff, &conf.task_path.join(SOCKET));
+    let interval = chrono::Duration::seconds(conf.hook_interval as i64);
+    let task = backend.reminder(
+        interval,
         &tags.unwrap_or_default(),
         &ntags.unwrap_or_default(),
     );
-    let items = store.select_random_from_list(&items, n, conf.cutoff.clone());
+    let _ = backend.save();
+    if let Ok(Some(task)) = task {
+        // Escape codes confuse shells measuring the width of a prompt
+        colored::control::set_override(false);
+        println!("{}", template.render(&task));
+    }
+}
 
-    print_tasks(&items);
-    if conf.debug {
-        dbg!(&items.len());
+fn run_complete_values(conf: &Config, kind: CompletionKind) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    match kind {
+        CompletionKind::Tasks => {
+            println!("last");
+            for task in backend.list(&ListArgs::default())? {
+                println!("{}", task.id());
+                println!("{}", task.name());
+            }
+        },
+        CompletionKind::Tags => {
+            for tag in backend.tags()? {
+                println!("{}", tag);
+            }
+        },
     }
+    return Ok(());
 }
 
-fn run_add(conf: &Config, args: &AddArgs) {
+fn run_restore(conf: &Config, list: bool, snapshot: Option<&str>) -> Result<(), TasksError> {
+    // Keeps `tasks serve` and other runs from writing over the restored files
     let store = Store::new(conf.task_path.clone());
-    let desc = match &args.description {
-        Some(desc) => Some(desc.as_str()),
+    store.hold_lock()?;
+    let names = snapshots(&conf.task_path)?;
+    let name = match snapshot {
+        Some("latest") => names.last().map(|name| name.as_str()),
+        Some(name) => Some(name),
         None => None,
     };
-
-    let task = Task::new_todo(
-        args.name.join(" "),
-        desc,
-        Some(args.priority),
-        Some(args.tag.to_owned()),
-    );
-    match store.append(task) {
-        Ok(task) => {
-            println!("Appended task:");
-            print_task(&task);
+    match name {
+        Some(name) if !list => {
+            let saved = restore(&conf.task_path, name)?;
+            println!(
+                "Restored {}. The previous state was saved as {}",
+                name, saved
+            );
+        },
+        _ => {
+            if names.is_empty() {
+                println!("No backups have been taken yet.");
+            }
+            for name in names.iter().rev() {
+                println!("{}", name);
+            }
         },
-        Err(err) => println!("Could not add task. {}", err.to_string()),
     }
+    return Ok(());
 }
 
-fn run_touch(conf: &Config, terms: &[String]) {
+fn run_fsck(conf: &Config, repair: bool) -> Result<(), TasksError> {
     let store = Store::new(conf.task_path.clone());
-    let found = store.fzf(terms);
-    match found {
-        None => {
-            println!("Could not find task!");
-            exit(1);
+    store.hold_lock()?;
+    let report = fsck(&conf.task_path, repair)?;
+    if report.is_clean() {
+        println!("No issues found.");
+        return Ok(());
+    }
+    println!("Found {} issue(s):", report.issues.len());
+    for issue in &report.issues {
+        println!("  {}", issue);
+    }
+    match &report.backup {
+        Some(backup) if report.repaired => {
+            println!("Repaired. Original files kept in {}", backup.display())
         },
-        Some(task) => {
-            println!("Editing:");
-            print_task(&task);
-            let task = update_item(store, task, conf, Task::touched);
-            println!("Touched: `{}`", task.name());
+        _ => {
+            if !report.is_repairable() {
+                println!(
+                    "Unreadable files must be fixed by hand, or restored with `tasks restore`."
+                );
+            } else {
+                println!("Run `tasks fsck --repair` to fix them.");
+            }
+            exit(EXIT_FSCK_ISSUES);
         },
     }
+    return Ok(());
 }
 
-fn run_complete(conf: &Config, terms: &[String]) {
-    let retired = retire_item(conf, terms);
-    println!("Now retired:");
-    print_task(&retired);
-}
-
-fn run_edit(conf: &Config, args: &EditArgs) {
-    let store = Store::new(conf.task_path.clone());
-    let found = store.fzf(args.identifier.as_ref());
-    match found {
-        None => {
-            println!("Could not find task!");
-            exit(1);
-        },
-        Some(task) => {
-            println!("Editing:");
-            print_task(&task);
-            print!("vvvvvvvvvvvv HAS BECOME vvvvvvvvvvvv\n");
-            let out = update_item(store, task, conf, |task| {
-                let mut task = task.to_owned();
-                if let Some(addtags) = &args.atag {
-                    task = task.add_tags(addtags.to_vec());
-                }
-                if let Some(rmtags) = &args.rtag {
-                    task = task.remove_tags(rmtags);
-                }
-                if let Some(settags) = &args.stag {
-                    task = task.set_tags(settags.to_vec());
-                }
-                task.updated_todo(
-                    args.description.as_deref(),
-                    args.priority.as_ref(),
-                    args.name.as_deref(),
+fn run_config(flag: Option<&Path>, command: &ConfigCommand) -> Result<(), TasksError> {
+    let path = config::target(flag);
+    match command {
+        ConfigCommand::Show { origin } => {
+            print!("{}", Config::layers(flag)?.show(*origin)?);
+        },
+        ConfigCommand::Get { key } => match Config::layers(flag)?.get(key) {
+            Some(Value::String(value)) => println!("{}", value),
+            Some(Value::Table(table)) => {
+                print!(
+                    "{}",
+                    toml::to_string(table).map_err(|e| TasksError::parse(key, e))?
                 )
-            });
-            print_task(&out);
+            },
+            Some(value) => println!("{}", value),
+            None => return Err(TasksError::NotFound(format!("`{}` is not set", key))),
+        },
+        ConfigCommand::Set { key, value } => {
+            config::set(&path, key, value)?;
+            println!("Set `{}` in {}", key, path.display());
+        },
+        ConfigCommand::Unset { key } => match config::unset(&path, key)? {
+            true => println!("Removed `{}` from {}", key, path.display()),
+            false => println!("`{}` is not set in {}", key, path.display()),
+        },
+        ConfigCommand::Edit => {
+            edit_file(&path)?;
+            return run_config(flag, &ConfigCommand::Validate);
         },
+        ConfigCommand::Validate => {
+            let problems = Config::layers(flag)?.problems();
+            if problems.is_empty() {
+                println!("No problems found.");
+                return Ok(());
+            }
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            return Err(TasksError::parse(
+                "config",
+                format!("{} problem(s) found", problems.len()),
+            ));
+        },
+        ConfigCommand::Path => println!("{}", path.display()),
+    }
+    return Ok(());
+}
+
+fn edit_file(path: &Path) -> Result<(), TasksError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(TasksError::io(dir))?;
+    }
+    if !path.exists() {
+        fs::write(path, "").map_err(TasksError::io(path))?;
+    }
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(TasksError::io(Path::new(program)))?;
+    if !status.success() {
+        let error = io::Error::other(format!("{} exited with {}", program, status));
+        return Err(TasksError::io(path)(error));
     }
+    return Ok(());
 }
 
-fn update_item<F>(store: Store, task: Task, conf: &Config, f: F) -> Task
-where
-    F: FnOnce(&Task) -> Task,
-{
+fn run_random(conf: &Config, template: &Template, args: &RandomArgs) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let items = backend.random(args)?;
+    backend.save()?;
+
+    print_tasks(template, &items);
+    if conf.debug {
+        dbg!(&items.len());
+    }
+    return Ok(());
+}
+
+fn run_add(conf: &Config, template: &Template, args: &AddArgs) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let task = backend.add(args)?;
+    backend.save()?;
+    println!("Appended task:");
+    print_task(template, &task);
+    return Ok(());
+}
+
+fn run_show(conf: &Config, template: &Template, terms: &[String]) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let task = backend.find_anywhere(terms)?;
+    let chance = backend.chance(task.id())?;
+    backend.save()?;
+    println!("{}", template.render_with_chance(&task, chance));
+    return Ok(());
+}
+
+fn run_merge(
+    conf: &Config,
+    template: &Template,
+    keep: &str,
+    duplicate: &str,
+) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let keep = backend.find(&[keep.to_string()])?;
+    let duplicate = backend.find(&[duplicate.to_string()])?;
+    let merged = backend.merge(&keep, &duplicate)?;
+    backend.save()?;
+    println!("Merged `{}` into:", duplicate.name());
+    print_task(template, &merged);
+    return Ok(());
+}
+
+fn run_note(
+    conf: &Config,
+    template: &Template,
+    terms: &[String],
+    text: &str,
+) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let task = backend.find(terms)?;
+    let task = backend.note(&task, text)?;
+    backend.save()?;
+    println!("Noted on:");
+    print_task(template, &task);
+    return Ok(());
+}
+
+fn run_touch(conf: &Config, template: &Template, terms: &[String]) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let task = backend.find(terms)?;
+    println!("Editing:");
+    print_task(template, &task);
     if conf.debug {
         dbg!(&task);
     }
-    let result = store.update_item(task, f);
+    let task = backend.touch(&task)?;
+    backend.save()?;
+    println!("Touched: `{}`", task.name());
+    return Ok(());
+}
+
+fn run_complete(conf: &Config, template: &Template, terms: &[String]) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let task = backend.find(terms)?;
+    let retired = backend.close(&task)?;
+    backend.save()?;
+    println!("Now retired:");
+    print_task(template, &retired);
+    return Ok(());
+}
+
+fn run_edit(conf: &Config, template: &Template, args: &EditArgs) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let task = backend.find(args.identifier.as_ref())?;
+    println!("Editing:");
+    print_task(template, &task);
+    print!("vvvvvvvvvvvv HAS BECOME vvvvvvvvvvvv\n");
     if conf.debug {
-        dbg!(&result);
+        dbg!(&task);
     }
-    match result {
-        Err(error) => {
-            println!("Failed to update item, {}", error.to_string());
-            exit(1);
-        },
-        Ok(task) => return task,
+    let out = backend.edit(&task, args);
+    if conf.debug {
+        dbg!(&out);
     }
+    let out = out?;
+    backend.save()?;
+    print_task(template, &out);
+    return Ok(());
 }
 
-fn retire_item(conf: &Config, terms: &[String]) -> Task {
-    let store = Store::new(conf.task_path.clone());
-    let item = store.fzf(terms);
-    match item {
-        None => {
-            println!("No task selected. Exiting");
-            exit(0);
-        },
-        Some(task) => {
-            let result = store.retire_item(&task);
-            match result {
-                Err(err) => {
-                    println!("{}", err.to_string());
-                    exit(1);
-                },
-                Ok(task) => {
-                    return task;
-                },
-            }
-        },
+fn run_focus(
+    conf: &Config,
+    template: &Template,
+    minutes: u32,
+    tags: Option<Vec<String>>,
+    ntags: Option<Vec<String>>,
+) -> Result<(), TasksError> {
+    focus::check_terminal()?;
+    // The store is let go of during the session, so that other commands can use it
+    let picked = {
+        let backend = open_backend(conf);
+        let picked = backend.random(&RandomArgs {
+            tags: tags.unwrap_or_default(),
+            ntags: ntags.unwrap_or_default(),
+            force: conf.cutoff == 0,
+            n: 1,
+            ..Default::default()
+        })?;
+        backend.save()?;
+        picked
+    };
+    let Some(task) = picked.into_iter().next() else {
+        println!("No tasks to focus on!");
+        return Ok(());
+    };
+    print_task(template, &task);
+    println!();
+    let start = chrono::Local::now();
+    let Some(end) = focus::countdown(&task, chrono::Duration::minutes(minutes as i64))? else {
+        println!("Session abandoned, nothing was logged");
+        return Ok(());
+    };
+    let snooze_until = chrono::Local::now() + chrono::Duration::seconds(conf.snooze as i64);
+    let outcome = focus::ask(snooze_until)?;
+    let session = Interval {
+        start,
+        end,
+        focus: Some(outcome.clone()),
+    };
+    let backend = open_backend(conf);
+    let task = backend.log_session(task.id(), &session)?;
+    backend.save()?;
+    println!(
+        "Logged {} on `{}`: {}",
+        duration::format(&session.duration_since(None)),
+        task.name(),
+        outcome
+    );
+    return Ok(());
+}
+
+fn run_start(conf: &Config, terms: &[String]) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let task = backend.find(terms)?;
+    let stopped = backend.start(&task)?;
+    backend.save()?;
+    if let Some(stopped) = stopped {
+        print_stopped(&stopped);
     }
+    println!("Started: `{}`", task.name());
+    return Ok(());
 }
 
-fn run_list(conf: &Config, args: &ListArgs) {
-    let store = Store::new(conf.task_path.clone());
-    let items: Vec<Task> = store
-        .filter_active(
-            &args.terms,
-            &args.tags.clone().unwrap_or_default(),
-            &args.ntags.clone().unwrap_or_default(),
-        )
-        .into_iter()
-        .collect();
+fn run_stop(conf: &Config) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let task = backend.stop()?;
+    backend.save()?;
+    print_stopped(&task);
+    return Ok(());
+}
+
+fn print_stopped(task: &Task) {
+    let spent = task
+        .intervals()
+        .last()
+        .map(|interval| interval.duration_since(None))
+        .unwrap_or_else(chrono::Duration::zero);
+    println!(
+        "Stopped: `{}` after {}",
+        task.name(),
+        duration::format(&spent)
+    );
+}
+
+fn run_report(conf: &Config, time: bool, since: Option<&str>) -> Result<(), TasksError> {
+    if !time {
+        println!("Nothing to report, try `tasks report --time`");
+        return Ok(());
+    }
+    let now = chrono::Local::now();
+    let since = since.map(|since| parse_since(since, &now)).transpose()?;
+    let backend = open_backend(conf);
+    let mut tasks = backend.list(&ListArgs::default())?;
+    tasks.extend(backend.closed()?);
+    let timer = backend.timer()?;
+    let report = TimeReport::new(&tasks, timer.as_ref(), since.as_ref(), &now);
+    if report.tasks.is_empty() {
+        println!("No time recorded, track some with `tasks start` and `tasks stop`");
+        return Ok(());
+    }
+    let width = |spent: &chrono::Duration| duration::format(spent).len();
+    let column = report
+        .tasks
+        .iter()
+        .map(|(_, spent)| spent)
+        .chain(report.tags.iter().map(|(_, spent)| spent))
+        .map(width)
+        .max()
+        .unwrap_or_default();
+    match since {
+        Some(since) => println!("Time since {}:", since.format("%Y-%m-%d %H:%M")),
+        None => println!("Time per task:"),
+    }
+    for (task, spent) in &report.tasks {
+        let running = timer.as_ref().is_some_and(|timer| timer.id == task.id());
+        println!(
+            "  {:>column$}  {}{}",
+            duration::format(spent),
+            task.name(),
+            if running { " (running)" } else { "" }
+        );
+    }
+    if !report.tags.is_empty() {
+        println!("Time per tag:");
+        for (tag, spent) in &report.tags {
+            println!("  {:>column$}  {}", duration::format(spent), tag);
+        }
+    }
+    println!("Total: {}", duration::format(&report.total));
+    return Ok(());
+}
+
+fn run_list(conf: &Config, template: &Template, args: &ListArgs) -> Result<(), TasksError> {
+    let backend = open_backend(conf);
+    let items = backend.list(args)?;
     if items.len() == 0 {
         println!(
             "{}",
@@ -235,21 +698,24 @@ fn run_list(conf: &Config, args: &ListArgs) {
             }
         );
     } else {
-        print_tasks(&items);
+        print_tasks(template, &items);
     }
-    exit(0);
+    return Ok(());
 }
 
-fn print_task(task: &Task) {
-    println!("{}", task)
+fn print_task(template: &Template, task: &Task) {
+    println!("{}", template.render(task))
 }
 
-fn print_tasks(tasks: &[Task]) {
+fn print_tasks(template: &Template, tasks: &[Task]) {
     if tasks.len() == 0 {
         return;
     }
-    println!("{}", tasks[0]);
+    print_task(template, &tasks[0]);
     for task in &tasks[1..] {
-        println!("\n{}", task)
+        if template.is_multiline() {
+            println!();
+        }
+        print_task(template, task);
     }
 }
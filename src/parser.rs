@@ -1,6 +1,15 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
 use clap::{Args, Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 
+use crate::completions::{CompletionKind, CompletionShell};
+use crate::error::TasksError;
+use crate::sort::{sort_tasks, SortKey};
+use crate::task::{Energy, Estimate, Task};
+use crate::window::Window;
+
 #[derive(Debug, Parser)]
 #[command(version, about, long_about=None)] // version|about filled in from cargo.toml
 pub struct Cli {
@@ -10,13 +19,19 @@ pub struct Cli {
 
     /// Runs command as tasks random -f 1
     #[arg(short, long)]
-    pub force: bool,
+    pub force:  bool,
     /// Must include tags - forces mode to RANDOM
     #[arg(short, long, value_delimiter = ',')]
-    pub tags:  Option<Vec<String>>,
+    pub tags:   Option<Vec<String>>,
     /// Must NOT include tags - forces mode to RANDOM
     #[arg(short, long, value_delimiter = ',')]
-    pub ntags: Option<Vec<String>>,
+    pub ntags:  Option<Vec<String>>,
+    /// Output template, or one of the presets `full`, `oneline` and `compact`
+    #[arg(long, global = true)]
+    pub format: Option<String>,
+    /// Config file read after every other layer
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
 
     #[command(subcommand)]
     pub command: Option<Commands>,
@@ -30,12 +45,45 @@ pub struct AddArgs {
     /// Modifier to time bias takes integer 0+
     #[arg(short, long)]
     #[arg(default_value_t = 100)]
+    #[serde(default = "default_priority")]
     pub priority:    u16,
     /// Task name
     pub name:        Vec<String>,
     /// Tags for the task
     #[arg(short, long, value_delimiter = ',')]
+    #[serde(default)]
     pub tag:         Vec<String>,
+    /// How long the task should take, e.g. `15m` or `1h30m`
+    #[arg(long)]
+    pub estimate:    Option<Estimate>,
+    /// How much energy the task takes
+    #[arg(long)]
+    pub energy:      Option<Energy>,
+    /// When the task can be done, e.g. `weekdays 9-17` or `weekends`
+    #[arg(long)]
+    pub window:      Option<Window>,
+    /// Adds the task even if an active task has nearly the same name
+    #[arg(short, long)]
+    #[serde(default)]
+    pub force:       bool,
+}
+
+fn default_priority() -> u16 {
+    return 100;
+}
+
+impl AddArgs {
+    pub fn task(&self) -> Task {
+        return Task::new_todo(
+            self.name.join(" "),
+            self.description.as_deref(),
+            Some(self.priority),
+            Some(self.tag.to_owned()),
+        )
+        .with_estimate(self.estimate)
+        .with_energy(self.energy)
+        .with_window(self.window);
+    }
 }
 // TODO:
 // change tag so that you can `--tag foo` and `-tag foo`
@@ -61,21 +109,99 @@ pub struct EditArgs {
     /// Sets the tags of the chosen todo
     #[arg(short, long, value_delimiter = ',')]
     pub stag:        Option<Vec<String>>,
+    /// New estimate, e.g. `15m` or `1h30m`
+    #[arg(long)]
+    pub estimate:    Option<Estimate>,
+    /// New energy level
+    #[arg(long)]
+    pub energy:      Option<Energy>,
+    /// New window, e.g. `weekdays 9-17`, or `always` to ignore the windows of its tags
+    #[arg(long)]
+    pub window:      Option<Window>,
     /// Identifier string
     #[arg(required = true)]
+    #[serde(default)]
     pub identifier:  Vec<String>,
 }
 
-#[derive(Debug, Args, Deserialize, Serialize, Clone)]
+impl EditArgs {
+    /// `task` with these edits applied
+    pub fn apply(&self, task: &Task) -> Task {
+        let mut task = task.to_owned();
+        if let Some(addtags) = &self.atag {
+            task = task.add_tags(addtags.to_vec());
+        }
+        if let Some(rmtags) = &self.rtag {
+            task = task.remove_tags(rmtags);
+        }
+        if let Some(settags) = &self.stag {
+            task = task.set_tags(settags.to_vec());
+        }
+        if self.estimate.is_some() {
+            task = task.with_estimate(self.estimate);
+        }
+        if self.energy.is_some() {
+            task = task.with_energy(self.energy);
+        }
+        if self.window.is_some() {
+            task = task.with_window(self.window);
+        }
+        return task.updated_todo(
+            self.description.as_deref(),
+            self.priority.as_ref(),
+            self.name.as_deref(),
+        );
+    }
+}
+
+#[derive(Debug, Args, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
 pub struct ListArgs {
     /// Must include tags
     #[arg(short, long, value_delimiter = ',')]
-    pub tags:  Option<Vec<String>>,
+    pub tags:    Option<Vec<String>>,
     /// Must NOT include tags
     #[arg(short, long, value_delimiter = ',')]
-    pub ntags: Option<Vec<String>>,
+    pub ntags:   Option<Vec<String>>,
+    /// Sort by name, priority, age, created or weight. Combine keys with commas, and prefix
+    /// one with `-` to reverse it, e.g. `priority,-age`. Defaults to `created`
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    pub sort:    Vec<SortKey>,
+    /// Reverses the whole order
+    #[arg(long)]
+    pub reverse: bool,
+    /// Prints at most this many tasks
+    #[arg(long)]
+    pub limit:   Option<usize>,
+    /// Skips this many tasks first
+    #[arg(long, default_value_t = 0)]
+    pub offset:  usize,
+    /// Only tasks estimated to fit in this much time, e.g. `15m`
+    #[arg(long)]
+    pub time:    Option<Estimate>,
+    /// Only tasks that need at most this much energy
+    #[arg(long)]
+    pub energy:  Option<Energy>,
     /// Filter search with provided terms
-    pub terms: Vec<String>,
+    pub terms:   Vec<String>,
+}
+
+impl ListArgs {
+    /// Sorts `tasks` (by `created` unless told otherwise), and picks out the page asked for
+    pub fn arrange(&self, mut tasks: Vec<Task>) -> Result<Vec<Task>, TasksError> {
+        match self.sort.is_empty() {
+            true => sort_tasks(&mut tasks, &["created".parse()?]),
+            false => sort_tasks(&mut tasks, &self.sort),
+        }
+        if self.reverse {
+            tasks.reverse();
+        }
+        return Ok(tasks
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect());
+    }
 }
 
 #[derive(Debug, Subcommand, Deserialize, Serialize, Clone)]
@@ -85,20 +211,50 @@ pub enum Commands {
     Add(AddArgs),
     /// Prints the last viewed task
     Last,
+    /// Folds a duplicate into a task: tags, descriptions and history are combined, and the
+    /// duplicate is closed
+    Merge {
+        /// The task to keep: its id, `last`, or a term to search for
+        keep:      String,
+        /// The task to fold into it and close
+        duplicate: String,
+    },
+    /// Appends a timestamped note to a task, keeping its description
+    #[command(alias = "n")]
+    Note {
+        #[arg(required = true)]
+        /// Task id, `last`, or terms to search for
+        terms: Vec<String>,
+        /// What to note down
+        text:  String,
+    },
+    /// Prints every detail of a task, active or closed
+    #[command(alias = "s")]
+    Show {
+        #[arg(required = true)]
+        /// Task id, `last`, or terms to search for
+        terms: Vec<String>,
+    },
     /// Produce a random task, with a bias for older tasks
     #[command(alias = "r")]
     Random {
         /// Must include tags
         #[arg(short, long, value_delimiter = ',')]
-        tags:  Option<Vec<String>>,
+        tags:   Option<Vec<String>>,
         /// Must NOT include tags
         #[arg(short, long, value_delimiter = ',')]
-        ntags: Option<Vec<String>>,
+        ntags:  Option<Vec<String>>,
         /// Sets random selection cutoff to 0 so that newly minted tasks can be selected
         #[arg(short, long)]
-        force: bool,
+        force:  bool,
+        /// Only tasks estimated to fit in this much time, e.g. `15m`
+        #[arg(long)]
+        time:   Option<Estimate>,
+        /// Only tasks that need at most this much energy
+        #[arg(long)]
+        energy: Option<Energy>,
         #[arg(default_value_t = 1)]
-        n:     u8,
+        n:      usize,
     },
     /// Edit an existing task
     #[command(alias = "e")]
@@ -128,4 +284,108 @@ pub enum Commands {
     /// Reverts the previous entry in the undo list (that changed bank state)
     #[command(alias = "u")]
     Undo,
+    /// Prints a one line reminder for a shell prompt or login script.
+    /// Rolls a new task at most once per `hook_interval`, and never blocks or prompts
+    Hook {
+        /// Must include tags
+        #[arg(short, long, value_delimiter = ',')]
+        tags:  Option<Vec<String>>,
+        /// Must NOT include tags
+        #[arg(short, long, value_delimiter = ',')]
+        ntags: Option<Vec<String>>,
+    },
+    /// Prints a shell completion script, e.g. `source <(tasks completions bash)`
+    Completions { shell: CompletionShell },
+    /// Lists values for shell completion
+    #[command(name = "__complete", hide = true)]
+    Complete { kind: CompletionKind },
+    /// Opens a full screen interface to browse and triage tasks
+    Tui,
+    /// Restores the task store from an automatic backup
+    Restore {
+        /// Lists the available snapshots
+        #[arg(short, long)]
+        list:     bool,
+        /// Snapshot to restore, or `latest`
+        #[arg(conflicts_with = "list")]
+        snapshot: Option<String>,
+    },
+    /// Checks the task store for inconsistencies
+    Fsck {
+        /// Repairs any issues found, keeping a backup of the original files
+        #[arg(short, long)]
+        repair: bool,
+    },
+    /// Picks a task at random to work on for a while, then asks how it went
+    Focus {
+        /// Length of the session
+        #[arg(short, long, default_value_t = 25)]
+        minutes: u32,
+        /// Must include tags
+        #[arg(short, long, value_delimiter = ',')]
+        tags:    Option<Vec<String>>,
+        /// Must NOT include tags
+        #[arg(short, long, value_delimiter = ',')]
+        ntags:   Option<Vec<String>>,
+        /// Sets random selection cutoff to 0 so that newly minted tasks can be selected
+        #[arg(short, long)]
+        force:   bool,
+    },
+    /// Starts timing a task, stopping the timer of any other task
+    Start {
+        #[arg(required = true)]
+        /// Terms to search for
+        terms: Vec<String>,
+    },
+    /// Stops the running timer, and counts it as a touch of its task
+    Stop,
+    /// Summarises the task store
+    Report {
+        /// Totals the time recorded by `start` and `stop`, per task and per tag
+        #[arg(long)]
+        time:  bool,
+        /// Only counts time after this: a date, an RFC 3339 time, or an amount like `7d`
+        #[arg(long, requires = "time")]
+        since: Option<String>,
+    },
+    /// Keeps the store open and serves it over JSON-RPC on a Unix socket. Other commands use it
+    /// while it runs
+    Serve {
+        /// Socket to listen on, `socket` in the task directory by default
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        /// Also serves a REST API on this address, e.g. `127.0.0.1:8080`
+        #[arg(long)]
+        http:   Option<SocketAddr>,
+    },
+    /// Runs a saved filter from the `[views]` table of the config, or lists them
+    #[command(alias = "v")]
+    View { name: Option<String> },
+    /// Inspects and changes the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Subcommand, Deserialize, Serialize, Clone)]
+pub enum ConfigCommand {
+    /// Prints the merged configuration
+    Show {
+        /// Notes which layer (default, file, environment variable) set each value
+        #[arg(long)]
+        origin: bool,
+    },
+    /// Prints the merged value of a setting, e.g. `cutoff` or `formats.list`
+    Get { key: String },
+    /// Sets a value in the config file, keeping its comments
+    Set { key: String, value: String },
+    /// Removes a setting from the config file
+    Unset { key: String },
+    /// Opens the config file in $VISUAL or $EDITOR, then validates it
+    Edit,
+    /// Checks for unknown keys, and that `task_path` is writable
+    Validate,
+    /// Prints the path of the config file that `set`, `unset` and `edit` change
+    Path,
 }
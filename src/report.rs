@@ -0,0 +1,143 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+
+use crate::duration;
+use crate::error::TasksError;
+use crate::store::Timer;
+use crate::task::{Interval, Task};
+
+/// Where time went, from the intervals recorded by `tasks start` and `tasks stop`
+#[derive(Debug)]
+pub struct TimeReport {
+    /// Tasks that were worked on, most time first
+    pub tasks: Vec<(Task, Duration)>,
+    /// Tags of those tasks, most time first. Time on a task counts towards each of its tags
+    pub tags:  Vec<(String, Duration)>,
+    pub total: Duration,
+}
+
+impl TimeReport {
+    /// Totals the time spent on `tasks` after `since`, including the running `timer` up to `now`
+    pub fn new(
+        tasks: &[Task],
+        timer: Option<&Timer>,
+        since: Option<&DateTime<Local>>,
+        now: &DateTime<Local>,
+    ) -> TimeReport {
+        let mut totals: Vec<(Task, Duration)> = tasks
+            .iter()
+            .map(|task| {
+                let mut spent = task
+                    .intervals()
+                    .iter()
+                    .map(|interval| interval.duration_since(since))
+                    .fold(Duration::zero(), |total, spent| total + spent);
+                if let Some(timer) = timer.filter(|timer| timer.id == task.id()) {
+                    let running = Interval {
+                        start: timer.started,
+                        end:   *now,
+                        focus: None,
+                    };
+                    spent += running.duration_since(since);
+                }
+                (task.clone(), spent)
+            })
+            .filter(|(_, spent)| *spent > Duration::zero())
+            .collect();
+        totals.sort_by_key(|(_, spent)| Reverse(*spent));
+
+        let mut tags: BTreeMap<String, Duration> = BTreeMap::new();
+        for (task, spent) in &totals {
+            for tag in task.tags() {
+                let total = tags.entry(tag.clone()).or_insert(Duration::zero());
+                *total += *spent;
+            }
+        }
+        let mut tags: Vec<(String, Duration)> = tags.into_iter().collect();
+        tags.sort_by_key(|(_, spent)| Reverse(*spent));
+
+        let total = totals
+            .iter()
+            .fold(Duration::zero(), |total, (_, spent)| total + *spent);
+        return TimeReport {
+            tasks: totals,
+            tags,
+            total,
+        };
+    }
+}
+
+/// Parses the start of a report: a date (`2024-05-01`), a date and time in RFC 3339, or an amount
+/// of time before `now` (`30m`, `12h`, `7d`, `2w`)
+pub fn parse_since(text: &str, now: &DateTime<Local>) -> Result<DateTime<Local>, TasksError> {
+    let invalid = || {
+        TasksError::parse(
+            "--since",
+            format!("`{}` is not a date, a time, or an amount like `7d`", text),
+        )
+    };
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Local));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        return Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .ok_or_else(invalid);
+    }
+    let ago = duration::parse(text).map_err(|_| invalid())?;
+    return now.checked_sub_signed(ago).ok_or_else(invalid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_per_task_and_tag() {
+        let now = Local::now();
+        let hours = |n| now - Duration::hours(n);
+        // Tasks made in the same millisecond would share an id
+        let task = |id: &str, tags: &str| -> Task {
+            let json = format!(
+                r#"{{"task": {{"Todo": {{}}}}, "tags": [{tags}], "metadata": {{"id": "{id}",
+                "description": null, "name": "{id}", "priority": 100, "created": "{now:?}",
+                "last_completed": "{now:?}", "closed_at": null}}}}"#
+            );
+            return serde_json::from_str(&json).unwrap();
+        };
+        let plants = task("plants", r#""home""#)
+            .tracked(hours(30), hours(28))
+            .tracked(hours(3), hours(2));
+        let dishes = task("dishes", r#""home""#);
+        let untracked = task("read", "");
+        let timer = Timer {
+            id:      dishes.id().to_string(),
+            started: now - Duration::minutes(30),
+        };
+
+        let tasks = [plants, dishes, untracked];
+        let report = TimeReport::new(&tasks, Some(&timer), None, &now);
+        assert_eq!(report.tasks.len(), 2);
+        assert_eq!(report.tasks[0].1, Duration::hours(3));
+        assert_eq!(report.tags, vec![("home".to_string(), report.total)]);
+        assert_eq!(duration::format(&report.total), "3h 30m");
+
+        let since = parse_since("1d", &now).unwrap();
+        let report = TimeReport::new(&tasks, Some(&timer), Some(&since), &now);
+        assert_eq!(duration::format(&report.total), "1h 30m");
+    }
+
+    #[test]
+    fn since() {
+        let now = Local::now();
+        assert_eq!(parse_since("2w", &now).unwrap(), now - Duration::days(14));
+        assert!(parse_since("2024-05-01", &now).is_ok());
+        assert!(parse_since("2024-05-01T10:00:00+02:00", &now).is_ok());
+        assert!(parse_since("soon", &now).is_err());
+        assert!(parse_since("", &now).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::cmp::Ordering;
+
+use rand::Rng;
+
+/// Picks up to `n` distinct indices into `weights`, each draw favouring an index in proportion
+/// to its weight among those not yet picked.
+///
+/// This is the A-ES algorithm of Efraimidis and Spirakis: every index gets the key `u^(1/w)` for
+/// a uniform `u`, and the `n` largest keys win. Keys are compared as `ln(u) / w` so that large
+/// weights do not round every key to one. Indices with no weight only come after all the others,
+/// in a uniformly random order, so that there is always something to pick.
+pub fn weighted<R: Rng + ?Sized>(weights: &[u64], n: usize, rng: &mut R) -> Vec<usize> {
+    let mut keys: Vec<(bool, f64, usize)> = weights
+        .iter()
+        .enumerate()
+        .map(|(index, weight)| {
+            // In (0, 1], so that the logarithm is finite
+            let u = 1.0 - rng.gen::<f64>();
+            let key = match *weight {
+                0 => u.ln(),
+                weight => u.ln() / weight as f64,
+            };
+            (*weight > 0, key, index)
+        })
+        .collect();
+    keys.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal))
+    });
+    return keys
+        .into_iter()
+        .take(n)
+        .map(|(_, _, index)| index)
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const ROUNDS: usize = 40_000;
+
+    // How often each index is picked over `ROUNDS` samples of `n`
+    fn frequencies(weights: &[u64], n: usize) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut counts = vec![0; weights.len()];
+        for _ in 0..ROUNDS {
+            let picked = weighted(weights, n, &mut rng);
+            assert_eq!(picked.len(), n.min(weights.len()));
+            for index in picked {
+                counts[index] += 1;
+            }
+        }
+        return counts
+            .into_iter()
+            .map(|count| count as f64 / ROUNDS as f64)
+            .collect();
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            // Over five standard deviations for these sample sizes
+            assert!(
+                (actual - expected).abs() < 0.015,
+                "{:?} is not {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn single_picks_follow_the_weights() {
+        assert_close(&frequencies(&[1, 2, 3, 4], 1), &[0.1, 0.2, 0.3, 0.4]);
+        // Weights in the range of seconds times priority
+        let big = [86_400 * 100, 3 * 86_400 * 100];
+        assert_close(&frequencies(&big, 1), &[0.25, 0.75]);
+    }
+
+    #[test]
+    fn picks_are_without_replacement() {
+        // The heavy one is first half the time, and second in a third of the rest
+        assert_close(
+            &frequencies(&[1, 1, 2], 2),
+            &[7.0 / 12.0, 7.0 / 12.0, 5.0 / 6.0],
+        );
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut picked = weighted(&[5; 300], 300, &mut rng);
+        picked.sort();
+        assert_eq!(picked, (0..300).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn weightless_indices_come_last() {
+        assert_close(&frequencies(&[0, 0, 0, 0], 1), &[0.25; 4]);
+        assert_close(&frequencies(&[0, 3, 0, 1], 3), &[0.5, 1.0, 0.5, 1.0]);
+        assert_eq!(frequencies(&[0, 0, 1], 1), vec![0.0, 0.0, 1.0]);
+        assert!(weighted(&[], 3, &mut StdRng::seed_from_u64(7)).is_empty());
+    }
+}
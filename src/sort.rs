@@ -0,0 +1,111 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::error::TasksError;
+use crate::task::Task;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum SortField {
+    /// Alphabetical
+    Name,
+    /// Lowest priority first
+    Priority,
+    /// Most recently touched first
+    Age,
+    /// Oldest first
+    Created,
+    /// Least likely to be picked by `tasks random` first
+    Weight,
+}
+
+/// One key of `tasks list --sort`, such as `priority`, or `-age` for the reverse order
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct SortKey {
+    pub field:      SortField,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = TasksError;
+
+    fn from_str(s: &str) -> Result<SortKey, TasksError> {
+        let (name, descending) = match s.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (s, false),
+        };
+        let field = match name {
+            "name" => SortField::Name,
+            "priority" => SortField::Priority,
+            "age" => SortField::Age,
+            "created" => SortField::Created,
+            "due" => {
+                return Err(TasksError::parse(
+                    "sort",
+                    "tasks have no due date to sort by",
+                ))
+            },
+            "weight" => SortField::Weight,
+            _ => {
+                return Err(TasksError::parse(
+                    "sort",
+                    format!(
+                        "unknown key `{}`, expected name, priority, age, created or weight",
+                        name
+                    ),
+                ))
+            },
+        };
+        return Ok(SortKey { field, descending });
+    }
+}
+
+impl SortKey {
+    fn compare(&self, a: &Task, b: &Task, now: &DateTime<Local>) -> Ordering {
+        let ordering = match self.field {
+            SortField::Name => a.name().to_lowercase().cmp(&b.name().to_lowercase()),
+            SortField::Priority => a.priority().cmp(b.priority()),
+            SortField::Age => b.last_touched().cmp(a.last_touched()),
+            SortField::Created => a.created().cmp(b.created()),
+            SortField::Weight => a.weight(now).cmp(&b.weight(now)),
+        };
+        return match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        };
+    }
+}
+
+/// Sorts by each of `keys` in turn, later keys breaking ties. The sort is stable
+pub fn sort_tasks(tasks: &mut [Task], keys: &[SortKey]) {
+    let now = Local::now();
+    tasks.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| key.compare(a, b, &now))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combined_keys() {
+        let task =
+            |name: &str, priority| Task::new_todo(name.to_string(), None, Some(priority), None);
+        let mut tasks = vec![task("b", 1), task("c", 2), task("a", 2)];
+        let keys: Vec<SortKey> = ["-priority", "name"]
+            .iter()
+            .map(|key| key.parse().unwrap())
+            .collect();
+        sort_tasks(&mut tasks, &keys);
+        let names: Vec<&str> = tasks.iter().map(|task| task.name()).collect();
+        assert_eq!(names, ["a", "c", "b"]);
+        assert!("size".parse::<SortKey>().is_err());
+        assert!("-due".parse::<SortKey>().is_err());
+    }
+}
@@ -1,24 +1,35 @@
-use std::cmp;
-use std::error::Error;
-use std::ffi::OsString;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions, TryLockError};
 use std::path::PathBuf;
+use std::time::Duration;
+use std::{cmp, thread};
 
 use fuzzy_finder::item::Item;
 use fuzzy_finder::FuzzyFinder;
-use rand::Rng;
 use undobank::UndoItem;
 
+mod backup;
 mod bank;
+mod fsck;
+mod hooks;
 mod last;
+mod schema;
 mod undobank;
 use std::cell::{RefCell, RefMut};
 
+pub use backup::{restore, snapshot, snapshots, Backups};
 use bank::Bank;
-use last::Last;
+pub use fsck::{fsck, Issue, Report};
+pub use hooks::{Event, Hooks, HOOKS};
+use last::{Last, Reminder, Roll};
+pub use last::{Repeats, Timer};
 use undobank::UndoBank;
 
-use crate::task::Task;
+use crate::error::TasksError;
+use crate::sample;
 pub use crate::task::TaskType;
+use crate::task::{Interval, Outcome, Task};
+use crate::window::Window;
 
 pub struct Store {
     directory: PathBuf,
@@ -26,40 +37,37 @@ pub struct Store {
     closed:    RefCell<Option<Bank>>,
     undo:      RefCell<Option<UndoBank>>,
     last:      RefCell<Option<Last>>,
+    backups:   Option<Backups>,
+    hooks:     Hooks,
+    // Default availability windows by tag
+    windows:   BTreeMap<String, Window>,
+    repeats:   Repeats,
+    // Post hooks waiting for the next save
+    pending:   RefCell<Vec<(Event, Option<Task>, Option<Task>)>>,
+    lock:      RefCell<Option<File>>,
+    lock_wait: bool,
 }
 
 const ACTIVE: &'static str = "active";
 const CLOSED: &'static str = "closed";
 const UNDO: &'static str = "undo";
 const LAST: &'static str = "last";
+const LOCK: &str = "lock";
 
-pub fn init_store(directory: &PathBuf) -> Result<(), Box<dyn Error>> {
-    std::fs::create_dir_all(&directory)?;
-    let mut dirs: Vec<Box<OsString>> = directory
-        .read_dir()
-        .expect("reading dir failed")
-        .filter_map(|r| match r {
-            Ok(dir) => Some(Box::new(dir.file_name())),
-            Err(_) => None,
-        })
-        .collect();
-    dirs.sort();
-    let files = [ACTIVE, CLOSED, UNDO, LAST];
-
-    let bank = Bank::empty();
-    for (i, file) in files.iter().enumerate() {
-        let file = directory.join(file);
-        let name = file.file_name().unwrap();
-        let name = Box::new(name.to_os_string());
-        let exists = dirs.binary_search(&name).is_ok();
-
-        if !exists {
-            match i {
-                0 | 1 => bank.to_file(&file)?,
-                2 => UndoBank::empty().to_file(&file)?,
-                3 => Last::default().to_file(&file)?,
-                _ => panic!("`i` beyond 3 not implemented"),
-            }
+const LOCK_ATTEMPTS: u32 = 50;
+const LOCK_WAIT: Duration = Duration::from_millis(100);
+
+pub fn init_store(directory: &PathBuf) -> Result<(), TasksError> {
+    std::fs::create_dir_all(directory).map_err(TasksError::io(directory))?;
+    for file in [ACTIVE, CLOSED, UNDO, LAST] {
+        let path = directory.join(file);
+        if path.exists() {
+            continue;
+        }
+        match file {
+            UNDO => UndoBank::empty().to_file(&path)?,
+            LAST => Last::default().to_file(&path)?,
+            _ => Bank::empty().to_file(&path)?,
         }
     }
     return Ok(());
@@ -73,95 +81,204 @@ impl Store {
             closed:    RefCell::from(None),
             undo:      RefCell::from(None),
             last:      RefCell::from(None),
+            backups:   None,
+            hooks:     Hooks::default(),
+            windows:   BTreeMap::new(),
+            repeats:   Repeats::default(),
+            pending:   RefCell::from(vec![]),
+            lock:      RefCell::from(None),
+            lock_wait: true,
         }
     }
 
-    fn load_active(&self) -> RefMut<Bank> {
-        let mut active = self.active.borrow_mut();
-        if let None = *active {
-            let bank = Bank::from_file(&self.directory.join(ACTIVE)).unwrap();
-            *active = Some(bank);
-        }
-        return std::cell::RefMut::map(active, |opt| opt.as_mut().unwrap());
+    pub fn with_backups(mut self, backups: Backups) -> Store {
+        self.backups = Some(backups);
+        return self;
+    }
+
+    pub fn with_hooks(mut self, hooks: Hooks) -> Store {
+        self.hooks = hooks;
+        return self;
+    }
+
+    /// Random selection skips tasks outside the window of their tags, for tasks without one
+    pub fn with_windows(mut self, windows: BTreeMap<String, Window>) -> Store {
+        self.windows = windows;
+        return self;
+    }
+
+    /// Random selection holds back the tasks it suggested lately, as `repeats` says
+    pub fn with_repeats(mut self, repeats: Repeats) -> Store {
+        self.repeats = repeats;
+        return self;
+    }
+
+    /// Fails straight away with `TasksError::Locked`, rather than waiting for another process
+    pub fn without_lock_wait(mut self) -> Store {
+        self.lock_wait = false;
+        return self;
     }
 
-    fn unload_active(&self) {
-        if let Some(active) = self.active.take() {
-            active.to_file(&self.directory.join(ACTIVE)).unwrap();
+    /// Takes the store lock without loading anything, for commands that write the files
+    /// themselves. It is held until the store is dropped
+    pub fn hold_lock(&self) -> Result<(), TasksError> {
+        return self.lock();
+    }
+
+    // Held from the first load until the store is dropped, so that
+    // concurrent runs cannot interleave their reads and writes
+    fn lock(&self) -> Result<(), TasksError> {
+        let mut lock = self.lock.borrow_mut();
+        if lock.is_some() {
+            return Ok(());
         }
+        let path = self.directory.join(LOCK);
+        // Not truncated, so that taking the lock does not count as a change to sync tools
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(TasksError::io(&path))?;
+        let attempts = if self.lock_wait { LOCK_ATTEMPTS } else { 1 };
+        for attempt in 1..=attempts {
+            match file.try_lock() {
+                Ok(()) => {
+                    *lock = Some(file);
+                    return Ok(());
+                },
+                Err(TryLockError::WouldBlock) if attempt < attempts => thread::sleep(LOCK_WAIT),
+                Err(TryLockError::WouldBlock) => break,
+                Err(TryLockError::Error(error)) => return Err(TasksError::io(&path)(error)),
+            }
+        }
+        return Err(TasksError::Locked(path));
     }
 
-    fn load_closed(&self) -> RefMut<Bank> {
-        let mut closed = self.closed.borrow_mut();
-        if let None = *closed {
-            let bank = Bank::from_file(&self.directory.join(CLOSED)).unwrap();
-            *closed = Some(bank);
+    fn load_active(&self) -> Result<RefMut<'_, Bank>, TasksError> {
+        let mut active = self.active.borrow_mut();
+        if active.is_none() {
+            self.lock()?;
+            *active = Some(Bank::from_file(&self.directory.join(ACTIVE))?);
         }
-        return std::cell::RefMut::map(closed, |opt| {
-            opt.as_mut().expect("Expected value but found None")
-        });
+        return Ok(RefMut::map(active, |opt| {
+            opt.get_or_insert_with(Bank::empty)
+        }));
     }
-    fn unload_closed(&self) {
-        let closed = self.closed.take();
-        if let Some(closed) = closed {
-            closed.to_file(&self.directory.join(CLOSED)).unwrap();
+
+    fn load_closed(&self) -> Result<RefMut<'_, Bank>, TasksError> {
+        let mut closed = self.closed.borrow_mut();
+        if closed.is_none() {
+            self.lock()?;
+            *closed = Some(Bank::from_file(&self.directory.join(CLOSED))?);
         }
+        return Ok(RefMut::map(closed, |opt| {
+            opt.get_or_insert_with(Bank::empty)
+        }));
     }
 
-    fn load_undo(&self) -> RefMut<UndoBank> {
+    fn load_undo(&self) -> Result<RefMut<'_, UndoBank>, TasksError> {
         let mut undo = self.undo.borrow_mut();
-        if let None = *undo {
-            let bank = UndoBank::from_file(&self.directory.join(UNDO)).unwrap();
-            *undo = Some(bank);
+        if undo.is_none() {
+            self.lock()?;
+            *undo = Some(UndoBank::from_file(&self.directory.join(UNDO))?);
         }
-        return std::cell::RefMut::map(undo, |opt| {
-            opt.as_mut().expect("Expected value but found None")
-        });
+        return Ok(RefMut::map(undo, |opt| {
+            opt.get_or_insert_with(UndoBank::empty)
+        }));
     }
 
-    fn unload_undo(&self) {
-        let undo = self.undo.take();
-        if let Some(undo) = undo {
-            undo.to_file(&self.directory.join(UNDO)).unwrap();
+    fn load_last(&self) -> Result<RefMut<'_, Last>, TasksError> {
+        let mut last = self.last.borrow_mut();
+        if last.is_none() {
+            self.lock()?;
+            *last = Some(Last::from_file(&self.directory.join(LAST))?);
         }
+        return Ok(RefMut::map(last, |opt| {
+            opt.get_or_insert_with(Last::default)
+        }));
     }
 
-    fn load_last(&self) -> RefMut<Last> {
+    /// Writes back every bank that has changed, then runs the post hooks of those changes.
+    /// Banks stay loaded, so a long running process does not read them again.
+    /// This also happens when the store is dropped, but errors can only be seen from here.
+    pub fn save(&self) -> Result<(), TasksError> {
+        let mut active = self.active.borrow_mut();
+        let mut closed = self.closed.borrow_mut();
+        let mut undo = self.undo.borrow_mut();
         let mut last = self.last.borrow_mut();
-        if let None = *last {
-            let bank = Last::from_file(&self.directory.join(LAST)).unwrap();
-            *last = Some(bank);
+        let active = active.as_mut().filter(|bank| bank.is_dirty());
+        let closed = closed.as_mut().filter(|bank| bank.is_dirty());
+        let undo = undo.as_mut().filter(|bank| bank.is_dirty());
+        let last = last.as_mut().filter(|last| last.is_dirty());
+        let dirty = active.is_some() || closed.is_some() || undo.is_some() || last.is_some();
+
+        let backed_up = match &self.backups {
+            Some(backups) if dirty => backups.before_write(&self.directory),
+            _ => Ok(()),
+        };
+        if let Some(active) = active {
+            active.to_file(&self.directory.join(ACTIVE))?;
+            active.mark_saved();
+        }
+        if let Some(undo) = undo {
+            undo.to_file(&self.directory.join(UNDO))?;
+            undo.mark_saved();
+        }
+        if let Some(closed) = closed {
+            closed.to_file(&self.directory.join(CLOSED))?;
+            closed.mark_saved();
         }
-        return std::cell::RefMut::map(last, |opt| {
-            opt.as_mut().expect("Expected value but found None")
-        });
-    }
-    fn unload_last(&self) {
-        let last = self.last.take();
         if let Some(last) = last {
-            last.to_file(&self.directory.join(LAST)).unwrap();
+            last.to_file(&self.directory.join(LAST))?;
+            last.mark_saved();
         }
+        for (event, old, new) in self.pending.take() {
+            self.hooks.post(event, old.as_ref(), new.as_ref());
+        }
+        return backed_up;
     }
 
-    pub fn undo(&self) -> Result<UndoItem, Box<dyn Error>> {
-        let mut last = self.load_last();
-        last.last = None;
+    /// Saves, then lets go of the banks and the lock, so that other processes can use the store
+    /// until the next load. For long running processes that only touch the store now and then
+    pub fn release(&self) -> Result<(), TasksError> {
+        self.save()?;
+        *self.active.borrow_mut() = None;
+        *self.closed.borrow_mut() = None;
+        *self.undo.borrow_mut() = None;
+        *self.last.borrow_mut() = None;
+        *self.lock.borrow_mut() = None;
+        return Ok(());
+    }
 
-        let mut undo = self.load_undo();
+    pub fn undo(&self) -> Result<UndoItem, TasksError> {
+        let mut last = self.load_last()?;
+        last.set(None);
 
+        let mut undo = self.load_undo()?;
+
+        if let Some(item) = undo.undoitems.last() {
+            for (old, new) in item.undone() {
+                self.hooks.pre(Event::Undo, old.as_ref(), new)?;
+            }
+        }
         let item = undo.pop();
         match item {
             Some(item) => {
                 self.undo_item(item.clone())?;
+                let mut pending = self.pending.borrow_mut();
+                for (old, new) in item.undone() {
+                    pending.push((Event::Undo, old, new));
+                }
                 return Ok(item);
             },
             None => {
-                return Err("No more undo items.".into());
+                return Err(TasksError::NotFound("No more undo items.".into()));
             },
         }
     }
 
-    fn undo_item(&self, item: UndoItem) -> Result<(), Box<dyn Error>> {
+    fn undo_item(&self, item: UndoItem) -> Result<(), TasksError> {
         match item {
             UndoItem::Add { new_task } => self.delete_item(ACTIVE, new_task)?,
             UndoItem::Move { task, from, to } => self.move_item(task, &to, &from)?,
@@ -169,7 +286,7 @@ impl Store {
                 from,
                 to: _,
                 in_bank,
-            } => self.force_update_item(in_bank.as_str(), from)?,
+            } => self.force_update_item(in_bank.as_str(), *from)?,
             UndoItem::Sequence(vec) => {
                 for item in vec.into_iter().rev() {
                     self.undo_item(item)?;
@@ -179,63 +296,80 @@ impl Store {
         return Ok(());
     }
 
-    pub fn move_item(
-        &self,
-        task: Task,
-        from_bank: &str,
-        to_bank: &str,
-    ) -> Result<(), Box<dyn Error>> {
+    pub fn move_item(&self, task: Task, from_bank: &str, to_bank: &str) -> Result<(), TasksError> {
         self.delete_item(from_bank, task.clone())?;
         self.add_item(task, to_bank)?;
         return Ok(());
     }
 
-    pub fn add_item(&self, task: Task, bank: &str) -> Result<(), Box<dyn Error>> {
+    pub fn add_item(&self, task: Task, bank: &str) -> Result<(), TasksError> {
         let mut bank = self.get_bank(bank)?;
         bank.append(task);
         return Ok(());
     }
 
-    pub fn delete_item(&self, bank: &str, task: Task) -> Result<(), Box<dyn Error>> {
+    pub fn delete_item(&self, bank: &str, task: Task) -> Result<(), TasksError> {
         let mut bank = self.get_bank(bank)?;
         bank.delete(task.id().into());
         return Ok(());
     }
 
-    pub fn force_update_item(&self, bank: &str, updated: Task) -> Result<(), Box<dyn Error>> {
+    pub fn force_update_item(&self, bank: &str, updated: Task) -> Result<(), TasksError> {
         let mut bank = self.get_bank(bank)?;
         bank.update(updated);
         return Ok(());
     }
 
-    pub fn get_bank(&self, bank_name: &str) -> Result<RefMut<Bank>, Box<dyn Error>> {
+    pub fn get_bank(&self, bank_name: &str) -> Result<RefMut<'_, Bank>, TasksError> {
         match bank_name {
-            ACTIVE => Ok(self.load_active()),
-            CLOSED => Ok(self.load_closed()),
-            _ => Err(format!("Invalid bank name: {}", bank_name).into()),
+            ACTIVE => self.load_active(),
+            CLOSED => self.load_closed(),
+            _ => Err(TasksError::InvalidBank(bank_name.to_string())),
         }
     }
 
-    pub fn retire_item(&self, task: &Task) -> Result<Task, Box<dyn Error>> {
-        let mut last = self.load_last();
-        let mut active = self.load_active();
-        let mut closed = self.load_closed();
-        let mut undo = self.load_undo();
+    pub fn retire_item(&self, task: &Task) -> Result<Task, TasksError> {
+        let mut last = self.load_last()?;
+        let mut active = self.load_active()?;
+        let mut closed = self.load_closed()?;
+        let mut undo = self.load_undo()?;
 
-        last.last = None;
+        last.set(None);
 
-        let completed = task.completed();
+        // Closing a task that is being timed stops its timer
+        let timer = last.timer.clone().filter(|timer| timer.id == task.id());
+        let completed = match &timer {
+            Some(timer) => {
+                let completed = task.completed();
+                completed.tracked(timer.started, *completed.last_touched())
+            },
+            None => task.completed(),
+        };
+        let completed = self
+            .hooks
+            .pre(Event::Close, Some(task), Some(completed.clone()))?
+            .unwrap_or(completed);
+        // A replacement still goes to the closed bank, so it must be closed
+        let completed = match completed.closed() {
+            Some(_) => completed,
+            None => completed.completed(),
+        };
 
         let ok = active.delete(task.id());
         if !ok {
-            return Err("Could not find task in active list".into());
+            return Err(TasksError::NotFound(
+                "Could not find task in active list".into(),
+            ));
+        }
+        if timer.is_some() {
+            last.set_timer(None);
         }
         closed.append(completed.clone());
         undo.append(UndoItem::Sequence(vec![
             UndoItem::Change {
                 in_bank: ACTIVE.to_string(),
-                from:    task.clone(),
-                to:      completed.clone(),
+                from:    Box::new(task.clone()),
+                to:      Box::new(completed.clone()),
             },
             UndoItem::Move {
                 task: completed.clone(),
@@ -243,194 +377,675 @@ impl Store {
                 to:   CLOSED.to_string(),
             },
         ]));
+        self.pending
+            .borrow_mut()
+            .push((Event::Close, Some(task.clone()), Some(completed.clone())));
         return Ok(completed);
     }
 
-    pub fn append(&self, task: Task) -> Result<Task, Box<dyn Error>> {
-        let mut active = self.load_active();
-        let mut last = self.load_last();
-        let mut undo = self.load_undo();
+    pub fn append(&self, task: Task) -> Result<Task, TasksError> {
+        // Locked before the hook runs, so that it sees the store as it is written to
+        let mut active = self.load_active()?;
+        let task = self
+            .hooks
+            .pre(Event::Add, None, Some(task.clone()))?
+            .unwrap_or(task);
+        let mut last = self.load_last()?;
+        let mut undo = self.load_undo()?;
 
         active.append(task.clone());
-        last.last = Some(task.id().to_string());
+        last.set(Some(task.id().to_string()));
 
         undo.append(UndoItem::Add {
             new_task: task.clone(),
         });
+        self.pending
+            .borrow_mut()
+            .push((Event::Add, None, Some(task.clone())));
         return Ok(task);
     }
 
-    pub fn fzf(&self, terms: &[String]) -> Option<Task> {
-        if let Some(task) = self.keyword_check(terms) {
-            return Some(task);
+    pub fn fzf(&self, terms: &[String]) -> Result<Task, TasksError> {
+        if let Some(task) = self.keyword_check(terms)? {
+            return Ok(task);
         }
-        let active = self.load_active();
-        match fzf_inner(&active, &terms) {
-            Some(task) => {
-                let mut last = self.load_last();
-                last.last = Some(task.id().to_string());
-                return Some(task);
-            },
-            None => return None,
+        let active = self.load_active()?;
+        let task = fzf_inner(&active, terms)?;
+        let mut last = self.load_last()?;
+        last.set(Some(task.id().to_string()));
+        return Ok(task);
+    }
+
+    /// Looks up an active task by its exact id, or `last`, and remembers it as the last task, as
+    /// `fzf` does. Never prompts
+    pub fn pick(&self, id: &str) -> Result<Task, TasksError> {
+        let terms = [id.to_string()];
+        let task =
+            match self.keyword_check(&terms)? {
+                Some(task) => task,
+                None => self.load_active()?.find(id).cloned().ok_or_else(|| {
+                    TasksError::NotFound(format!("No active task with id {}", id))
+                })?,
+            };
+        self.load_last()?.set(Some(task.id().to_string()));
+        return Ok(task);
+    }
+
+    /// Looks up a task by its exact id, active or closed. Unlike `pick`, `last` is left alone
+    pub fn get(&self, id: &str) -> Result<Task, TasksError> {
+        if let Some(task) = self.load_active()?.find(id) {
+            return Ok(task.clone());
         }
+        return self
+            .load_closed()?
+            .find(id)
+            .cloned()
+            .ok_or_else(|| TasksError::NotFound(format!("No task with id {}", id)));
     }
 
-    pub fn filter_active(&self, terms: &[String], tags: &[String], ntags: &[String]) -> Vec<Task> {
-        if let Some(task) = self.keyword_check(terms) {
-            return vec![task];
+    pub fn filter_active(
+        &self,
+        terms: &[String],
+        tags: &[String],
+        ntags: &[String],
+    ) -> Result<Vec<Task>, TasksError> {
+        if let Some(task) = self.keyword_check(terms)? {
+            return Ok(vec![task]);
         }
-        let active = self.load_active();
+        let active = self.load_active()?;
         let terms = terms
             .iter()
             .map(|string| string.as_str())
             .collect::<Vec<&str>>();
-        active
+        let found = active
             .iter()
             .filter(|task| task.mass_contains(&terms))
             .filter(|task| tags.iter().all(|tag| task.is_tagged(tag)))
             .filter(|task| !ntags.iter().any(|ntag| task.is_tagged(ntag)))
             .cloned()
-            .collect()
+            .collect();
+        return Ok(found);
+    }
+
+    /// Every tag used by an active task, sorted and without duplicates
+    pub fn tags(&self) -> Result<Vec<String>, TasksError> {
+        let active = self.load_active()?;
+        let mut tags: Vec<String> = active
+            .iter()
+            .flat_map(|task| task.tags().iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        return Ok(tags);
+    }
+
+    /// Folds `duplicate` into `keep`, as `Task::merged` does, and closes `duplicate`. Both are
+    /// undone at once
+    pub fn merge(&self, keep: &Task, duplicate: &Task) -> Result<Task, TasksError> {
+        if keep.id() == duplicate.id() {
+            return Err(TasksError::parse(
+                "merge",
+                "a task cannot be merged into itself",
+            ));
+        }
+        let start = self.load_undo()?.undoitems.len();
+        let merged = self.update_item(keep.clone(), |task| task.merged(duplicate))?;
+        if let Err(error) = self.retire_item(duplicate) {
+            // Takes back the update without running undo hooks, or the post-update hook of a
+            // change that never happened
+            self.load_undo()?.pop();
+            self.pending.borrow_mut().pop();
+            self.force_update_item(ACTIVE, keep.clone())?;
+            return Err(error);
+        }
+        self.load_undo()?.group_since(start);
+        return Ok(merged);
+    }
+
+    /// Active tasks named like `name`, see `Task::resembles`
+    pub fn resembling(&self, name: &str) -> Result<Vec<Task>, TasksError> {
+        return Ok(self
+            .load_active()?
+            .iter()
+            .filter(|task| task.resembles(name))
+            .cloned()
+            .collect());
     }
 
-    pub fn update_item<F>(&self, task: Task, f: F) -> Result<Task, Box<dyn Error>>
+    pub fn update_item<F>(&self, task: Task, f: F) -> Result<Task, TasksError>
     where
         F: FnOnce(&Task) -> Task,
     {
-        let mut undo = self.load_undo();
-        let mut active = self.load_active();
+        let mut undo = self.load_undo()?;
+        let mut active = self.load_active()?;
 
         let transformed = f(&task);
-        active.update(transformed.clone());
+        let transformed = self
+            .hooks
+            .pre(Event::Update, Some(&task), Some(transformed.clone()))?
+            .unwrap_or(transformed);
+        let ok = active.update(transformed.clone());
+        if !ok {
+            return Err(TasksError::NotFound(format!(
+                "No active task with id {}",
+                transformed.id()
+            )));
+        }
         undo.append(UndoItem::Change {
-            from:    task.clone(),
-            to:      transformed.clone(),
+            from:    Box::new(task.clone()),
+            to:      Box::new(transformed.clone()),
             in_bank: ACTIVE.to_string(),
         });
+        self.pending
+            .borrow_mut()
+            .push((Event::Update, Some(task), Some(transformed.clone())));
         return Ok(transformed);
     }
 
-    pub fn select_random_from_list(&self, list: &[Task], n: u8, cutoff: u64) -> Vec<Task> {
-        let mut last = self.load_last();
+    /// The running timer, if any
+    pub fn timer(&self) -> Result<Option<Timer>, TasksError> {
+        return Ok(self.load_last()?.timer.clone());
+    }
+
+    /// Starts timing `task`. A timer running on another task is stopped first, and that task is
+    /// returned
+    pub fn start(&self, task: &Task) -> Result<Option<Task>, TasksError> {
+        let stopped = match self.timer()? {
+            Some(_) => match self.stop() {
+                Ok(stopped) => Some(stopped),
+                // The timed task is gone, and so is its timer
+                Err(TasksError::NotFound(_)) => None,
+                Err(error) => return Err(error),
+            },
+            None => None,
+        };
+        self.load_last()?.set_timer(Some(Timer {
+            id:      task.id().to_string(),
+            started: chrono::Local::now(),
+        }));
+        return Ok(stopped);
+    }
+
+    /// Stops the running timer, recording the time spent on its task. This counts as a touch
+    pub fn stop(&self) -> Result<Task, TasksError> {
+        let timer = self
+            .timer()?
+            .ok_or_else(|| TasksError::NotFound("No timer is running".into()))?;
+        let task = self.load_active()?.find(&timer.id).cloned();
+        let Some(task) = task else {
+            self.load_last()?.set_timer(None);
+            return Err(TasksError::NotFound(format!(
+                "The timed task {} is no longer active",
+                timer.id
+            )));
+        };
+        let now = chrono::Local::now();
+        let task = self.update_item(task, |task| task.tracked(timer.started, now).touched())?;
+        self.load_last()?.set_timer(None);
+        return Ok(task);
+    }
+
+    /// Logs a focus `session` on the active task with this id, and acts on how it went: done
+    /// touches the task, closed closes it, and snoozed snoozes it
+    pub fn log_session(&self, id: &str, session: Interval) -> Result<Task, TasksError> {
+        let task = self
+            .load_active()?
+            .find(id)
+            .cloned()
+            .ok_or_else(|| TasksError::NotFound(format!("No active task with id {}", id)))?;
+        return match session.focus.clone() {
+            // Undoing the close brings back the task with the session logged
+            Some(Outcome::Closed) => self.retire_item(&task.logged(session)),
+            Some(Outcome::Done) => self.update_item(task, |task| task.logged(session).touched()),
+            Some(Outcome::Snoozed { until }) => {
+                self.update_item(task, |task| task.logged(session).snoozed(until))
+            },
+            Some(Outcome::Skipped) | None => self.update_item(task, |task| task.logged(session)),
+        };
+    }
+
+    /// Picks up to `n` distinct tasks from `list`, favouring tasks by their weight. Snoozed tasks,
+    /// tasks outside their availability window, and tasks touched less than `cutoff` seconds ago
+    /// are left out. Tasks suggested lately, as `with_repeats` says, are only picked once nothing
+    /// else is left, longest ago first
+    pub fn select_random_from_list(
+        &self,
+        list: &[Task],
+        n: usize,
+        cutoff: u64,
+    ) -> Result<Vec<Task>, TasksError> {
+        let mut last = self.load_last()?;
+        let now = chrono::Local::now();
+        let mut rng = rand::thread_rng();
+        let mut chosen: Vec<Task> = vec![];
+        for group in self.candidates(list, cutoff, &last, &now) {
+            let weights: Vec<u64> = group.iter().map(|task| task.weight(&now)).collect();
+            let picked = sample::weighted(&weights, n - chosen.len(), &mut rng);
+            chosen.extend(picked.into_iter().map(|i| group[i].clone()));
+        }
+        if chosen.len() == 1 {
+            last.set(Some(chosen[0].id().to_string()));
+        } else {
+            last.set(None);
+        }
+        if !chosen.is_empty() {
+            let ids = chosen.iter().map(|task| task.id().to_string()).collect();
+            last.remember(
+                Roll {
+                    ids,
+                    rolled_at: now,
+                },
+                &self.repeats,
+            );
+        }
+
+        return Ok(chosen);
+    }
+
+    /// How likely a plain `tasks random` is to pick the task with `id` next, or `None` if it is not
+    /// active
+    pub fn chance(&self, id: &str, cutoff: u64) -> Result<Option<f64>, TasksError> {
+        let tasks = self.load_active()?.tasks.clone();
+        if !tasks.iter().any(|task| task.id() == id) {
+            return Ok(None);
+        }
         let now = chrono::Local::now();
-        let mappings: Vec<(u64, usize)> = list
+        let last = self.load_last()?;
+        let groups = self.candidates(&tasks, cutoff, &last, &now);
+        // Only the first group is drawn from, unless it runs out
+        let group = groups
+            .first()
+            .map(|group| group.as_slice())
+            .unwrap_or_default();
+        let Some(task) = group.iter().find(|task| task.id() == id) else {
+            return Ok(Some(0.0));
+        };
+        let weight = |task: &Task| task.weight(&now) as f64;
+        let total: f64 = group.iter().map(|task| weight(task)).sum();
+        if total == 0.0 {
+            return Ok(Some(1.0 / group.len() as f64));
+        }
+        return Ok(Some(weight(task) / total));
+    }
+
+    // The tasks of `list` random selection may pick, in the groups it picks from in turn: tasks
+    // not suggested lately, then the ones suggested longest ago
+    fn candidates<'a>(
+        &self,
+        list: &'a [Task],
+        cutoff: u64,
+        last: &Last,
+        now: &chrono::DateTime<chrono::Local>,
+    ) -> Vec<Vec<&'a Task>> {
+        let mut groups: BTreeMap<Option<usize>, Vec<&Task>> = BTreeMap::new();
+        let eligible = list
             .iter()
-            .enumerate()
-            .filter_map(|(idx, task)| {
+            .filter(|task| !task.is_snoozed(now))
+            .filter(|task| task.is_available(now, &self.windows))
+            .filter(|task| {
                 let duration_passed: u64 = now
                     .signed_duration_since(task.last_touched())
                     .num_seconds()
                     .try_into()
-                    .unwrap();
-                if duration_passed < cutoff {
-                    return None;
-                }
-                let priority = task.priority();
-                let weight = duration_passed * (*priority as u64);
-                return Some((idx, weight));
-            })
-            .scan(0 as u64, |counter, (idx, duration_passed)| {
-                *counter += duration_passed;
-                Some((counter.clone(), idx))
-            })
-            .collect();
-        if mappings.len() == 0 {
-            return vec![];
-        }
-        let mut chosen: Vec<usize> = vec![];
-        let mut rng = rand::thread_rng();
-        let max_mapping = mappings[mappings.len() - 1].0;
-        for _ in 0..(cmp::min(n as usize, mappings.len())) {
-            loop {
-                let chosen_passed: u64 = rng.gen_range((0 as u64)..=max_mapping);
-
-                let loc =
-                    match mappings.binary_search_by_key(&chosen_passed, |(passed, _idx)| *passed) {
-                        Ok(loc) => loc,
-                        Err(loc) => loc,
-                    };
-
-                if chosen.contains(&loc) {
-                    continue;
-                }
-                chosen.push(loc);
-                break;
-            }
-        }
-        let chosen: Vec<Task> = chosen.iter().map(|i| list[*i].clone()).collect();
-        if chosen.len() == 1 {
-            last.last = Some(chosen[0].id().to_string());
-        } else {
-            last.last = None;
+                    .unwrap_or(0);
+                duration_passed >= cutoff
+            });
+        for task in eligible {
+            let roll = last.suggested_in(task.id(), &self.repeats, now);
+            groups.entry(roll).or_default().push(task);
         }
+        return groups.into_values().collect();
+    }
 
-        return chosen;
+    pub fn select_random(&self, n: usize, cutoff: u64) -> Result<Vec<Task>, TasksError> {
+        let tasks = self.load_active()?.tasks.clone();
+        return self.select_random_from_list(&tasks, n, cutoff);
     }
 
-    pub fn select_random(&self, n: u8, cutoff: u64) -> Vec<Task> {
-        let active = self.load_active();
-        return self.select_random_from_list(&active.tasks, n, cutoff);
+    /// The task to remind about from a shell prompt or login hook.
+    /// A new task is rolled at most once per `interval`; until then the same one is returned,
+    /// as long as it is still active, matches `tags` and `ntags`, is not snoozed, and is in its
+    /// window.
+    pub fn reminder(
+        &self,
+        interval: chrono::Duration,
+        tags: &[String],
+        ntags: &[String],
+        cutoff: u64,
+    ) -> Result<Option<Task>, TasksError> {
+        let now = chrono::Local::now();
+        let current = self.load_last()?.reminder.clone();
+        if let Some(reminder) = current {
+            if reminder.rolled_at + interval > now {
+                let active = self.load_active()?;
+                let task = active.find(&reminder.id).filter(|task| {
+                    tags.iter().all(|tag| task.is_tagged(tag))
+                        && !ntags.iter().any(|ntag| task.is_tagged(ntag))
+                        && !task.is_snoozed(&now)
+                        && task.is_available(&now, &self.windows)
+                });
+                if let Some(task) = task {
+                    return Ok(Some(task.clone()));
+                }
+            }
+        }
+        let items = self.filter_active(&[], tags, ntags)?;
+        let chosen = self.select_random_from_list(&items, 1, cutoff)?;
+        let task = chosen.into_iter().next();
+        let mut last = self.load_last()?;
+        last.set_reminder(task.as_ref().map(|task| Reminder {
+            id:        task.id().to_string(),
+            rolled_at: now,
+        }));
+        return Ok(task);
     }
 
-    fn keyword_check(&self, terms: &[String]) -> Option<Task> {
+    fn keyword_check(&self, terms: &[String]) -> Result<Option<Task>, TasksError> {
         if terms.len() != 1 {
-            return None;
+            return Ok(None);
         }
         let term: &str = terms[0].as_ref();
         if term.to_lowercase() == "last" {
-            let last = self.load_last();
-            let active = self.load_active();
+            let last = self.load_last()?;
+            let active = self.load_active()?;
             match &last.last {
-                None => return None,
+                None => return Ok(None),
                 Some(id) => {
                     let found = active.find(&id);
                     let found = found.to_owned().cloned();
-                    return found;
+                    return Ok(found);
                 },
             }
         }
-        return None;
+        return Ok(None);
     }
 }
 
 impl Drop for Store {
     fn drop(&mut self) {
-        self.unload_active();
-        self.unload_undo();
-        self.unload_closed();
-        self.unload_last();
+        if let Err(error) = self.save() {
+            eprintln!("Could not save tasks: {}", error);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn focus_sessions_are_logged() {
+        let dir = std::env::temp_dir().join(format!("tasks-store-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let store = Store::new(dir.clone());
+        let task = Task::new_todo("water plants".to_string(), None, None, None);
+        let task = store.append(task).unwrap();
+        let session = |focus| {
+            let end = chrono::Local::now();
+            Interval {
+                start: end - chrono::Duration::minutes(25),
+                end,
+                focus: Some(focus),
+            }
+        };
+
+        let done = store
+            .log_session(task.id(), session(Outcome::Done))
+            .unwrap();
+        assert_eq!(done.intervals().len(), 1);
+        assert!(done.last_touched() > task.last_touched());
+
+        let closed = store.log_session(task.id(), session(Outcome::Closed));
+        assert!(closed.unwrap().closed().is_some());
+        assert!(store.get_bank(ACTIVE).unwrap().find(task.id()).is_none());
+        store.undo().unwrap();
+        let reopened = store.pick(task.id()).unwrap();
+        assert_eq!(reopened.intervals().len(), 2);
+        assert!(reopened.closed().is_none());
+
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_active_tasks_are_updated() {
+        let dir = std::env::temp_dir().join(format!("tasks-update-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let store = Store::new(dir.clone());
+        let task = Task::new_todo("water plants".to_string(), None, None, None);
+        let closed = store.retire_item(&store.append(task).unwrap()).unwrap();
+        let undone = store.load_undo().unwrap().undoitems.len();
+
+        let touched = store.update_item(closed.clone(), Task::touched);
+        assert!(matches!(touched, Err(TasksError::NotFound(_))));
+        assert_eq!(store.load_undo().unwrap().undoitems.len(), undone);
+        assert!(store.get(closed.id()).unwrap().closed().is_some());
+
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_merges_are_rolled_back() {
+        let dir = std::env::temp_dir().join(format!("tasks-merge-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let store = Store::new(dir.clone());
+        let new = |name: &str, tag: &str| {
+            let task = Task::new_todo(name.to_string(), None, None, Some(vec![tag.to_string()]));
+            store.append(task).unwrap()
+        };
+        let keep = new("water the plants", "home");
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let duplicate = store.retire_item(&new("water plants", "garden")).unwrap();
+        let undone = store.load_undo().unwrap().undoitems.len();
+        let pending = store.pending.borrow().len();
+
+        let merged = store.merge(&keep, &duplicate);
+        assert!(matches!(merged, Err(TasksError::NotFound(_))));
+        assert_eq!(store.pick(keep.id()).unwrap().tags(), keep.tags());
+        assert_eq!(store.load_undo().unwrap().undoitems.len(), undone);
+        assert_eq!(store.pending.borrow().len(), pending);
+
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reminders_follow_the_filter() {
+        let dir = std::env::temp_dir().join(format!("tasks-reminder-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let store = Store::new(dir.clone());
+        let new = |name: &str, tag: &str| {
+            let task = Task::new_todo(name.to_string(), None, None, Some(vec![tag.to_string()]));
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            store.append(task).unwrap()
+        };
+        let home = new("water plants", "home");
+        let work = new("file reports", "work");
+        let hour = chrono::Duration::hours(1);
+        let remind = |tag: &str| {
+            let tags = [tag.to_string()];
+            store.reminder(hour, &tags, &[], 0).unwrap().unwrap()
+        };
+
+        assert_eq!(remind("home").id(), home.id());
+        assert_eq!(remind("work").id(), work.id());
+        let later = chrono::Local::now() + hour;
+        store.update_item(work, |task| task.snoozed(later)).unwrap();
+        let everything = store.reminder(hour, &[], &[], 0).unwrap().unwrap();
+        assert_eq!(everything.id(), home.id());
+
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn released_stores_can_be_shared() {
+        let dir = std::env::temp_dir().join(format!("tasks-release-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let store = Store::new(dir.clone());
+        let task = Task::new_todo("water plants".to_string(), None, None, None);
+        store.append(task).unwrap();
+        let other = || Store::new(dir.clone()).without_lock_wait().tags();
+        assert!(matches!(other(), Err(TasksError::Locked(_))));
+
+        store.release().unwrap();
+        assert_eq!(other().unwrap(), Vec::<String>::new());
+        assert_eq!(store.filter_active(&[], &[], &[]).unwrap().len(), 1);
+
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn undone_merges_reach_hooks_per_task() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("tasks-undo-hooks-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let log = dir.join("log");
+        let hook = dir.join("hook");
+        let script = format!(
+            "#!/bin/sh\ncat >> {}\necho >> {}\n",
+            log.display(),
+            log.display()
+        );
+        std::fs::write(&hook, script).unwrap();
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let commands = BTreeMap::from([("pre-undo".to_string(), hook)]);
+        let store = Store::new(dir.clone()).with_hooks(Hooks { commands });
+        let keep = store
+            .append(Task::new_todo(
+                "water the plants".to_string(),
+                None,
+                None,
+                None,
+            ))
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let duplicate = store
+            .append(Task::new_todo("water plants".to_string(), None, None, None))
+            .unwrap();
+        store.merge(&keep, &duplicate).unwrap();
+        store.undo().unwrap();
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        let mut ids: Vec<String> = log
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|payload| {
+                let id = |task: &serde_json::Value| task["metadata"]["id"].clone();
+                assert_eq!(id(&payload["old"]), id(&payload["new"]), "{}", payload);
+                id(&payload["new"]).as_str().unwrap().to_string()
+            })
+            .collect();
+        ids.sort();
+        let mut expected = vec![keep.id().to_string(), duplicate.id().to_string()];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn close_hooks_cannot_reopen() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("tasks-close-hook-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let task = Task::new_todo("water plants".to_string(), None, None, None);
+        let task = Store::new(dir.clone()).append(task).unwrap();
+        // Replaces the closed task by the open one
+        let hook = dir.join("hook");
+        let json = serde_json::to_string(&task).unwrap();
+        let script = format!("#!/bin/sh\ncat > /dev/null\necho '{}'\n", json);
+        std::fs::write(&hook, script).unwrap();
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let commands = BTreeMap::from([("pre-close".to_string(), hook)]);
+        let store = Store::new(dir.clone()).with_hooks(Hooks { commands });
+
+        let closed = store.retire_item(&store.pick(task.id()).unwrap()).unwrap();
+        assert!(closed.closed().is_some());
+        assert!(store.get(task.id()).unwrap().closed().is_some());
+
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recent_suggestions_are_held_back() {
+        let dir = std::env::temp_dir().join(format!("tasks-recent-{}", std::process::id()));
+        init_store(&dir).unwrap();
+        let store = Store::new(dir.clone()).with_repeats(Repeats {
+            rolls:  2,
+            window: chrono::Duration::zero(),
+        });
+        let tasks: Vec<Task> = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                // Ids are milliseconds
+                thread::sleep(Duration::from_millis(2));
+                Task::new_todo(name.to_string(), None, None, None)
+            })
+            .collect();
+
+        let mut rolled: Vec<String> = vec![];
+        for _ in 0..6 {
+            let task = store.select_random_from_list(&tasks, 1, 0).unwrap();
+            assert!(!rolled.iter().rev().take(2).any(|id| id == task[0].id()));
+            rolled.push(task[0].id().to_string());
+        }
+        // Held back tasks still come up once nothing else is left, longest ago first
+        let all: Vec<String> = store
+            .select_random_from_list(&tasks, 3, 0)
+            .unwrap()
+            .iter()
+            .map(|task| task.id().to_string())
+            .collect();
+        assert_eq!(all, rolled[3..]);
+
+        drop(store);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
 
 // Assumes you've already checked for `last`
-fn fzf_inner(bank: &Bank, terms: &[String]) -> Option<Task> {
+fn fzf_inner(bank: &Bank, terms: &[String]) -> Result<Task, TasksError> {
     let terms = terms
         .iter()
         .map(|string| string.as_str())
         .collect::<Vec<&str>>();
-    let items: Vec<Item<Task>> = bank
+    let tasks = bank
         .iter()
         .filter(|task| task.mass_contains(&terms))
         .cloned()
+        .collect();
+    return choose(tasks);
+}
+
+/// Picks one of `tasks`: the only one, or the one chosen in an interactive fuzzy finder
+pub fn choose(tasks: Vec<Task>) -> Result<Task, TasksError> {
+    let items: Vec<Item<Task>> = tasks
+        .into_iter()
         .map(|task| Item::new(task.name().to_string(), task))
         .collect();
     let item = match items.len() {
         0 => None,
         1 => items[0].item.clone(),
         len => {
-            let len: i8 = len.try_into().unwrap();
-            let fzf = FuzzyFinder::find(items, len.clamp(1, 20));
-            match fzf {
+            let lines = cmp::min(len, 20) as i8;
+            match FuzzyFinder::find(items, lines) {
                 Ok(opt) => opt,
-                Err(err) => panic!("{}", err.to_string()),
+                Err(error) => {
+                    return Err(TasksError::Ambiguous {
+                        matches: len,
+                        reason:  error.to_string(),
+                    })
+                },
             }
         },
     };
 
-    return item;
+    return item.ok_or(TasksError::NotFound("No task selected".into()));
 }
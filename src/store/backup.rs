@@ -0,0 +1,168 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{ACTIVE, CLOSED, LAST, UNDO};
+use crate::error::TasksError;
+
+const BACKUPS: &str = "backups";
+const COUNTER: &str = "writes";
+const FILES: [&str; 4] = [ACTIVE, CLOSED, UNDO, LAST];
+
+/// When to snapshot the store, and how many snapshots to keep
+#[derive(Debug, Clone)]
+pub struct Backups {
+    /// Snapshot after this many writes, even within the same day. 0 disables this trigger
+    pub every: u32,
+    /// Number of snapshots to retain. 0 disables backups entirely
+    pub keep:  usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+struct Counter {
+    #[serde(default)]
+    writes: u32,
+}
+
+impl Counter {
+    fn from_file(path: &PathBuf) -> Counter {
+        return std::fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_yml::from_str(&s).ok())
+            .unwrap_or_default();
+    }
+
+    fn to_file(&self, file: &PathBuf) -> Result<(), TasksError> {
+        let s = serde_yml::to_string(self).map_err(|e| TasksError::parse(file.display(), e))?;
+        let mut f = std::fs::File::create(file).map_err(TasksError::io(file))?;
+        f.write_all(s.as_bytes()).map_err(TasksError::io(file))?;
+        return Ok(());
+    }
+}
+
+impl Backups {
+    /// Called before the store writes its files.
+    /// Takes a snapshot if none exists for today, or if `every` writes have passed since the last one.
+    pub fn before_write(&self, directory: &Path) -> Result<(), TasksError> {
+        if self.keep == 0 {
+            return Ok(());
+        }
+        let counter_path = directory.join(BACKUPS).join(COUNTER);
+        let mut counter = Counter::from_file(&counter_path);
+        let today = chrono::Local::now().format("%Y%m%d").to_string();
+        let stale = match snapshots(directory)?.last() {
+            Some(latest) => !latest.starts_with(&today),
+            None => true,
+        };
+        if stale || (self.every > 0 && counter.writes >= self.every) {
+            snapshot(directory)?;
+            prune(directory, self.keep)?;
+            counter.writes = 0;
+        }
+        counter.writes += 1;
+        counter.to_file(&counter_path)?;
+        return Ok(());
+    }
+}
+
+/// Names of all snapshots, oldest first
+pub fn snapshots(directory: &Path) -> Result<Vec<String>, TasksError> {
+    let backups = directory.join(BACKUPS);
+    if !backups.exists() {
+        return Ok(vec![]);
+    }
+    let mut names: Vec<String> = backups
+        .read_dir()
+        .map_err(TasksError::io(&backups))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    return Ok(names);
+}
+
+/// Copies the current store files into a new timestamped snapshot, returning its name
+pub fn snapshot(directory: &Path) -> Result<String, TasksError> {
+    let mut name = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut target = directory.join(BACKUPS).join(&name);
+    let mut suffix = 1;
+    while target.exists() {
+        name = format!(
+            "{}.{}",
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            suffix
+        );
+        target = directory.join(BACKUPS).join(&name);
+        suffix += 1;
+    }
+    std::fs::create_dir_all(&target).map_err(TasksError::io(&target))?;
+    for file in FILES {
+        let source = directory.join(file);
+        if source.exists() {
+            std::fs::copy(&source, target.join(file)).map_err(TasksError::io(&source))?;
+        }
+    }
+    return Ok(name);
+}
+
+/// Replaces the store files with those in `name`, which must be one of the `snapshots`.
+/// The current state is snapshotted first, so a restore can itself be restored away.
+pub fn restore(directory: &Path, name: &str) -> Result<String, TasksError> {
+    // Anything else, like `..`, could name the store itself
+    if !snapshots(directory)?
+        .iter()
+        .any(|snapshot| snapshot == name)
+    {
+        return Err(TasksError::NotFound(format!("No such snapshot: {}", name)));
+    }
+    let source = directory.join(BACKUPS).join(name);
+    let saved = snapshot(directory)?;
+    for file in FILES {
+        let from = source.join(file);
+        if from.exists() {
+            std::fs::copy(&from, directory.join(file)).map_err(TasksError::io(&from))?;
+        }
+    }
+    return Ok(saved);
+}
+
+fn prune(directory: &Path, keep: usize) -> Result<(), TasksError> {
+    let names = snapshots(directory)?;
+    if names.len() <= keep {
+        return Ok(());
+    }
+    for name in &names[..(names.len() - keep)] {
+        let path = directory.join(BACKUPS).join(name);
+        std::fs::remove_dir_all(&path).map_err(TasksError::io(&path))?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_snapshots_are_restored() {
+        let dir = std::env::temp_dir().join(format!("tasks-backup-{}", std::process::id()));
+        crate::store::init_store(&dir).unwrap();
+        std::fs::write(dir.join(ACTIVE), "tasks: []\n").unwrap();
+        let name = snapshot(&dir).unwrap();
+
+        for name in ["..", ".", "", "../backups", "nope"] {
+            assert!(
+                matches!(restore(&dir, name), Err(TasksError::NotFound(_))),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            std::fs::read_to_string(dir.join(ACTIVE)).unwrap(),
+            "tasks: []\n"
+        );
+        assert!(restore(&dir, &name).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
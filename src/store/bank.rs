@@ -1,14 +1,16 @@
-use std::error::Error;
-use std::io::Write;
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use super::schema::{self, Kind};
+use crate::error::TasksError;
 use crate::task::Task;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Bank {
     pub tasks: Vec<Task>,
+    #[serde(skip)]
+    dirty:     bool,
 }
 
 impl Bank {
@@ -16,6 +18,7 @@ impl Bank {
         for (i, task) in self.tasks.iter().enumerate() {
             if task.id() == updated_task.id() {
                 self.tasks[i] = updated_task;
+                self.dirty = true;
                 return true;
             }
         }
@@ -23,7 +26,8 @@ impl Bank {
     }
 
     pub fn append(&mut self, task: Task) {
-        self.tasks.push(task)
+        self.tasks.push(task);
+        self.dirty = true;
     }
 
     pub fn delete<'a>(&mut self, task_id: &'a str) -> bool {
@@ -31,6 +35,7 @@ impl Bank {
             let id = &task.id();
             if *id == task_id {
                 self.tasks.swap_remove(i);
+                self.dirty = true;
                 return true;
             }
         }
@@ -46,23 +51,33 @@ impl Bank {
         return None;
     }
 
+    pub fn new(tasks: Vec<Task>) -> Bank {
+        return Bank {
+            tasks,
+            dirty: false,
+        };
+    }
+
     pub fn empty() -> Bank {
-        return Bank { tasks: vec![] };
+        return Bank::new(vec![]);
     }
 
-    pub fn from_file(path: &PathBuf) -> Result<Bank, Box<dyn Error>> {
-        let s = std::fs::read_to_string(path)?;
-        let bank: Bank = serde_yml::from_str(s.as_str())?;
-        return Ok(bank);
+    /// True if the bank has changed since it was loaded
+    pub fn is_dirty(&self) -> bool {
+        return self.dirty;
     }
 
-    pub fn to_file<'a>(&self, file: &PathBuf) -> Result<(), Box<dyn Error>> {
-        let s = serde_yml::to_string(self)?;
-        let mut f = std::fs::File::create(file)?;
+    /// Called once the bank has been written back
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+    }
 
-        f.write_all(s.as_bytes())?;
+    pub fn from_file(path: &PathBuf) -> Result<Bank, TasksError> {
+        return schema::read(path, Kind::Bank);
+    }
 
-        return Ok(());
+    pub fn to_file<'a>(&self, file: &PathBuf) -> Result<(), TasksError> {
+        return schema::write(self, file);
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &Task> {
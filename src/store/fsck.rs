@@ -0,0 +1,274 @@
+use core::fmt;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+use super::bank::Bank;
+use super::last::Last;
+use super::undobank::{UndoBank, UndoItem};
+use super::{ACTIVE, CLOSED, LAST, UNDO};
+use crate::error::TasksError;
+use crate::task::Task;
+
+#[derive(Debug, Clone)]
+pub enum Issue {
+    Unreadable { file: String, error: String },
+    DuplicateId { bank: String, id: String },
+    InBothBanks { id: String },
+    ClosedWithoutTimestamp { id: String },
+    ActiveWithTimestamp { id: String },
+    FutureTimestamp { bank: String, id: String },
+    DanglingUndo { index: usize, id: String },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Unreadable { file, error } => write!(f, "`{file}` could not be read: {error}"),
+            Issue::DuplicateId { bank, id } => {
+                write!(f, "id {id} appears more than once in {bank}")
+            },
+            Issue::InBothBanks { id } => write!(f, "id {id} is in both {ACTIVE} and {CLOSED}"),
+            Issue::ClosedWithoutTimestamp { id } => {
+                write!(f, "closed task {id} has no `closed_at`")
+            },
+            Issue::ActiveWithTimestamp { id } => write!(f, "active task {id} has a `closed_at`"),
+            Issue::FutureTimestamp { bank, id } => {
+                write!(f, "task {id} in {bank} has a timestamp in the future")
+            },
+            Issue::DanglingUndo { index, id } => {
+                write!(f, "undo item #{index} refers to missing task {id}")
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub issues:   Vec<Issue>,
+    /// Directory the original files were copied to before repairing
+    pub backup:   Option<PathBuf>,
+    pub repaired: bool,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        return self.issues.is_empty();
+    }
+
+    pub fn is_repairable(&self) -> bool {
+        return !self
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, Issue::Unreadable { .. }));
+    }
+}
+
+/// Checks the store in `directory` for inconsistencies, optionally repairing them.
+///
+/// The bank files are read directly rather than through `Store`, so that a
+/// malformed file is reported instead of panicking.
+/// Repairs are only attempted if every file parses; the originals are copied
+/// into a `fsck-<timestamp>` directory first.
+pub fn fsck(directory: &Path, repair: bool) -> Result<Report, TasksError> {
+    let mut report = Report::default();
+    let active = read(directory, ACTIVE, Bank::from_file, &mut report);
+    let closed = read(directory, CLOSED, Bank::from_file, &mut report);
+    let undo = read(directory, UNDO, UndoBank::from_file, &mut report);
+    read(directory, LAST, Last::from_file, &mut report);
+    let (Some(mut active), Some(mut closed), Some(mut undo)) = (active, closed, undo) else {
+        return Ok(report);
+    };
+
+    let now = chrono::Local::now();
+    report.issues.extend(check(&active, &closed, &undo, &now));
+    if !repair || report.is_clean() || !report.is_repairable() {
+        return Ok(report);
+    }
+
+    report.backup = Some(backup(directory, &now)?);
+    fix(&mut active, &mut closed, &mut undo, &now);
+    active.to_file(&directory.join(ACTIVE))?;
+    closed.to_file(&directory.join(CLOSED))?;
+    undo.to_file(&directory.join(UNDO))?;
+    report.repaired = true;
+    return Ok(report);
+}
+
+fn read<T>(
+    directory: &Path,
+    name: &str,
+    reader: fn(&PathBuf) -> Result<T, TasksError>,
+    report: &mut Report,
+) -> Option<T> {
+    match reader(&directory.join(name)) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            report.issues.push(Issue::Unreadable {
+                file:  name.to_string(),
+                error: error.to_string(),
+            });
+            None
+        },
+    }
+}
+
+fn backup(directory: &Path, now: &DateTime<Local>) -> Result<PathBuf, TasksError> {
+    let target = directory.join(format!("fsck-{}", now.format("%Y%m%d%H%M%S")));
+    std::fs::create_dir_all(&target).map_err(TasksError::io(&target))?;
+    for file in [ACTIVE, CLOSED, UNDO, LAST] {
+        let source = directory.join(file);
+        if source.exists() {
+            std::fs::copy(&source, target.join(file)).map_err(TasksError::io(&source))?;
+        }
+    }
+    return Ok(target);
+}
+
+fn check(active: &Bank, closed: &Bank, undo: &UndoBank, now: &DateTime<Local>) -> Vec<Issue> {
+    let mut issues = vec![];
+    for (name, bank) in [(ACTIVE, active), (CLOSED, closed)] {
+        let mut seen = HashSet::new();
+        for task in bank.iter() {
+            if !seen.insert(task.id()) {
+                issues.push(Issue::DuplicateId {
+                    bank: name.to_string(),
+                    id:   task.id().to_string(),
+                });
+            }
+            if task.is_from_future(now) {
+                issues.push(Issue::FutureTimestamp {
+                    bank: name.to_string(),
+                    id:   task.id().to_string(),
+                });
+            }
+        }
+    }
+    for task in active.iter() {
+        if closed.find(task.id()).is_some() {
+            issues.push(Issue::InBothBanks {
+                id: task.id().to_string(),
+            });
+        }
+        if task.closed().is_some() {
+            issues.push(Issue::ActiveWithTimestamp {
+                id: task.id().to_string(),
+            });
+        }
+    }
+    for task in closed.iter() {
+        if task.closed().is_none() {
+            issues.push(Issue::ClosedWithoutTimestamp {
+                id: task.id().to_string(),
+            });
+        }
+    }
+    for (index, item) in undo.undoitems.iter().enumerate() {
+        if let Some(id) = dangling_id(item, active, closed) {
+            issues.push(Issue::DanglingUndo { index, id });
+        }
+    }
+    return issues;
+}
+
+fn fix(active: &mut Bank, closed: &mut Bank, undo: &mut UndoBank, now: &DateTime<Local>) {
+    dedup(active);
+    dedup(closed);
+    active.tasks.retain(|task| closed.find(task.id()).is_none());
+    active.tasks = active
+        .iter()
+        .map(|task| task.reopened().clamped_to(now))
+        .collect();
+    closed.tasks = closed
+        .iter()
+        .map(|task| match task.closed() {
+            Some(_) => task.clamped_to(now),
+            None => task.closed_at_last_touch().clamped_to(now),
+        })
+        .collect();
+    undo.undoitems
+        .retain(|item| dangling_id(item, active, closed).is_none());
+}
+
+// Keeps the most recently touched copy of each id, in its original position
+fn dedup(bank: &mut Bank) {
+    let mut keep: Vec<Task> = vec![];
+    for task in bank.tasks.drain(..) {
+        match keep.iter_mut().find(|kept| kept.id() == task.id()) {
+            Some(kept) => {
+                if task.last_touched() > kept.last_touched() {
+                    *kept = task;
+                }
+            },
+            None => keep.push(task),
+        }
+    }
+    bank.tasks = keep;
+}
+
+fn dangling_id(item: &UndoItem, active: &Bank, closed: &Bank) -> Option<String> {
+    let exists = |task: &Task| active.find(task.id()).is_some() || closed.find(task.id()).is_some();
+    let missing = |task: &Task| match exists(task) {
+        true => None,
+        false => Some(task.id().to_string()),
+    };
+    match item {
+        UndoItem::Add { new_task } => missing(new_task),
+        UndoItem::Move { task, .. } => missing(task),
+        UndoItem::Change { to, .. } => missing(to),
+        UndoItem::Sequence(items) => items
+            .iter()
+            .find_map(|item| dangling_id(item, active, closed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, last_completed: &str, closed_at: Option<&str>) -> Task {
+        let closed_at = closed_at.unwrap_or("null");
+        let yaml = format!(
+            "task: !Todo {{}}\nmetadata:\n  id: '{id}'\n  description: null\n  name: {id}\n  \
+             priority: 100\n  created: 2024-01-01T00:00:00+00:00\n  last_completed: \
+             {last_completed}\n  closed_at: {closed_at}\ntags: []\n"
+        );
+        return serde_yml::from_str(&yaml).unwrap();
+    }
+
+    const PAST: &str = "2024-01-02T00:00:00+00:00";
+    const FUTURE: &str = "2999-01-01T00:00:00+00:00";
+
+    #[test]
+    fn clean_store_has_no_issues() {
+        let a = task("1", PAST, None);
+        let active = Bank::new(vec![a.clone()]);
+        let closed = Bank::new(vec![task("2", PAST, Some(PAST))]);
+        let undo = UndoBank::new(vec![UndoItem::Add { new_task: a }]);
+        assert!(check(&active, &closed, &undo, &chrono::Local::now()).is_empty());
+    }
+
+    #[test]
+    fn repair_resolves_every_issue() {
+        let now = chrono::Local::now();
+        let mut active = Bank::new(vec![
+            task("1", PAST, None),
+            task("1", PAST, None),
+            task("2", PAST, Some(PAST)),
+            task("3", FUTURE, None),
+        ]);
+        let mut closed = Bank::new(vec![task("2", PAST, None)]);
+        let mut undo = UndoBank::new(vec![UndoItem::Add {
+            new_task: task("4", PAST, None),
+        }]);
+        let issues = check(&active, &closed, &undo, &now);
+        assert_eq!(issues.len(), 6);
+
+        fix(&mut active, &mut closed, &mut undo, &now);
+        assert!(check(&active, &closed, &undo, &now).is_empty());
+        assert_eq!(active.tasks.len(), 2);
+        assert_eq!(closed.tasks.len(), 1);
+        assert!(undo.undoitems.is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use serde::Serialize;
+
+use super::undobank::UndoItem;
+use crate::error::TasksError;
+use crate::task::Task;
+
+/// Names of the hooks that can be configured
+pub const HOOKS: [&str; 8] = [
+    "pre-add",
+    "post-add",
+    "pre-update",
+    "post-update",
+    "pre-close",
+    "post-close",
+    "pre-undo",
+    "post-undo",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    Add,
+    Update,
+    Close,
+    Undo,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Event::Add => "add",
+            Event::Update => "update",
+            Event::Close => "close",
+            Event::Undo => "undo",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+/// What a hook reads on stdin
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: Event,
+    old:   Option<&'a Task>,
+    new:   Option<&'a Task>,
+}
+
+/// Executables run around every change to the store, keyed by hook name (`pre-add`, ...).
+///
+/// Each is run with its hook name as its only argument, and reads
+/// `{"event": ..., "old": <task or null>, "new": <task or null>}` on stdin.
+/// A pre hook vetoes the change by exiting non-zero, with what it printed as the reason.
+/// Otherwise it may print a replacement for the new task as JSON, which must keep its id.
+/// Post hooks run once the change has been written, and cannot affect it
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub commands: BTreeMap<String, PathBuf>,
+}
+
+impl Hooks {
+    /// Runs the pre hook for `event`, returning `new`, or the task the hook replaced it with
+    pub fn pre(
+        &self,
+        event: Event,
+        old: Option<&Task>,
+        new: Option<Task>,
+    ) -> Result<Option<Task>, TasksError> {
+        let name = format!("pre-{}", event);
+        let Some(output) = self.run(&name, event, old, new.as_ref())? else {
+            return Ok(new);
+        };
+        if !output.status.success() {
+            let reason = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            return Err(TasksError::Hook {
+                hook:    name,
+                message: match reason.is_empty() {
+                    true => format!("refused the change ({})", output.status),
+                    false => format!("refused the change: {}", reason),
+                },
+            });
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() || event == Event::Undo {
+            return Ok(new);
+        }
+        let replacement: Task = serde_json::from_str(&stdout).map_err(|e| TasksError::Hook {
+            hook:    name.clone(),
+            message: format!("printed a task that could not be read: {}", e),
+        })?;
+        if new.as_ref().is_some_and(|new| new.id() != replacement.id()) {
+            return Err(TasksError::Hook {
+                hook:    name,
+                message: "changed the id of the task".to_string(),
+            });
+        }
+        return Ok(Some(replacement));
+    }
+
+    /// Runs the post hook for `event`. Its exit status and output are ignored, and as the change
+    /// is already saved, a hook that cannot be run is only reported on stderr
+    pub fn post(&self, event: Event, old: Option<&Task>, new: Option<&Task>) {
+        if let Err(error) = self.run(&format!("post-{}", event), event, old, new) {
+            eprintln!("Warning: {}", error);
+        }
+    }
+
+    fn run(
+        &self,
+        name: &str,
+        event: Event,
+        old: Option<&Task>,
+        new: Option<&Task>,
+    ) -> Result<Option<std::process::Output>, TasksError> {
+        let Some(command) = self.commands.get(name) else {
+            return Ok(None);
+        };
+        let failed = |message: String| TasksError::Hook {
+            hook: name.to_string(),
+            message,
+        };
+        let payload = serde_json::to_string(&Payload { event, old, new })
+            .map_err(|e| failed(e.to_string()))?;
+        let mut child = Command::new(command)
+            .arg(name)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| failed(format!("could not run {}: {}", command.display(), e)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // A hook that does not read its input is fine
+            let _ = stdin.write_all(payload.as_bytes());
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| failed(e.to_string()))?;
+        return Ok(Some(output));
+    }
+}
+
+impl UndoItem {
+    /// Each task this item changes, before and after it is undone, as hooks see them. A sequence
+    /// changing the same task several times reports it once
+    pub fn undone(&self) -> Vec<(Option<Task>, Option<Task>)> {
+        match self {
+            UndoItem::Add { new_task } => vec![(Some(new_task.clone()), None)],
+            UndoItem::Move { task, .. } => vec![(Some(task.clone()), Some(task.clone()))],
+            UndoItem::Change { from, to, .. } => vec![(Some(*to.clone()), Some(*from.clone()))],
+            UndoItem::Sequence(items) => {
+                // In the order they are undone: the first state seen is the current one, and the
+                // last is what the task goes back to
+                let mut pairs: Vec<(Option<Task>, Option<Task>)> = vec![];
+                for (old, new) in items.iter().rev().flat_map(UndoItem::undone) {
+                    let id = old
+                        .as_ref()
+                        .or(new.as_ref())
+                        .map(|task| task.id().to_string());
+                    let seen = pairs
+                        .iter_mut()
+                        .find(|(seen, _)| seen.as_ref().map(|task| task.id().to_string()) == id);
+                    match seen {
+                        Some(pair) => pair.1 = new,
+                        None => pairs.push((old, new)),
+                    }
+                }
+                pairs
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn hook(name: &str, script: &str) -> Hooks {
+        let path = std::env::temp_dir().join(format!("tasks-hook-{}-{}", std::process::id(), name));
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let commands = BTreeMap::from([(name.to_string(), path)]);
+        return Hooks { commands };
+    }
+
+    #[test]
+    fn pre_hooks_veto_and_replace() {
+        let task = Task::new_todo("water plants".to_string(), None, None, None);
+
+        let veto = hook("pre-add", "echo no tags; exit 1");
+        let error = veto.pre(Event::Add, None, Some(task.clone())).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "pre-add hook refused the change: no tags"
+        );
+
+        let replaced = task.add_tags(vec!["home".to_string()]);
+        let json = serde_json::to_string(&replaced).unwrap();
+        let replace = hook("pre-update", &format!("cat > /dev/null; echo '{}'", json));
+        let result = replace.pre(Event::Update, Some(&task), Some(task.clone()));
+        assert_eq!(result.unwrap().unwrap().tags(), &vec!["home".to_string()]);
+
+        // No hook configured for this event
+        let result = replace.pre(Event::Close, Some(&task), Some(task.clone()));
+        assert!(result.unwrap().unwrap().tags().is_empty());
+        for path in veto.commands.values().chain(replace.commands.values()) {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -1,34 +1,129 @@
-use std::error::Error;
-use std::io::Write;
 use std::path::PathBuf;
 
+use chrono::{DateTime, Duration, Local};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+use super::schema::{self, Kind};
+use crate::error::TasksError;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Last {
     #[serde(default)]
-    pub last: Option<String>,
+    pub last:     Option<String>,
+    /// The task `tasks hook` is currently reminding about
+    #[serde(default)]
+    pub reminder: Option<Reminder>,
+    /// The task `tasks start` is timing. Only one runs at a time
+    #[serde(default)]
+    pub timer:    Option<Timer>,
+    /// What random selection suggested lately, oldest first
+    #[serde(default)]
+    pub recent:   Vec<Roll>,
+    #[serde(skip)]
+    dirty:        bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Reminder {
+    pub id:        String,
+    pub rolled_at: DateTime<Local>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Timer {
+    pub id:      String,
+    pub started: DateTime<Local>,
+}
+
+/// Tasks suggested together by one random selection
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Roll {
+    pub ids:       Vec<String>,
+    pub rolled_at: DateTime<Local>,
+}
+
+/// How long random selection holds back tasks it suggested: for the last `rolls` rolls, and for
+/// `window` after they were suggested
+#[derive(Debug, Clone, Default)]
+pub struct Repeats {
+    pub rolls:  usize,
+    pub window: Duration,
 }
 
+// Bounds the file however long `window` is
+const RECENT_LIMIT: usize = 100;
+
 impl Last {
-    pub fn from_file(path: &PathBuf) -> Result<Last, Box<dyn Error>> {
-        let s = std::fs::read_to_string(path)?;
-        let last: Last = serde_yml::from_str(s.as_str())?;
-        return Ok(last);
+    pub fn set(&mut self, last: Option<String>) {
+        if self.last != last {
+            self.last = last;
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_reminder(&mut self, reminder: Option<Reminder>) {
+        if self.reminder != reminder {
+            self.reminder = reminder;
+            self.dirty = true;
+        }
     }
 
-    pub fn to_file<'a>(&self, file: &PathBuf) -> Result<(), Box<dyn Error>> {
-        let s = serde_yml::to_string(self)?;
-        let mut f = std::fs::File::create(file)?;
+    pub fn set_timer(&mut self, timer: Option<Timer>) {
+        if self.timer != timer {
+            self.timer = timer;
+            self.dirty = true;
+        }
+    }
 
-        f.write_all(s.as_bytes())?;
+    /// Remembers `roll`, and forgets the rolls that `repeats` no longer looks at
+    pub fn remember(&mut self, roll: Roll, repeats: &Repeats) {
+        let now = roll.rolled_at;
+        self.recent.push(roll);
+        let kept = self.recent.len().saturating_sub(repeats.rolls);
+        let mut index = 0;
+        self.recent.retain(|roll| {
+            index += 1;
+            index > kept || roll.rolled_at + repeats.window > now
+        });
+        let excess = self.recent.len().saturating_sub(RECENT_LIMIT);
+        self.recent.drain(..excess);
+        self.dirty = true;
+    }
 
-        return Ok(());
+    /// The latest roll that suggested the task with `id`, counting from the oldest, if `repeats`
+    /// still holds the task back
+    pub fn suggested_in(
+        &self,
+        id: &str,
+        repeats: &Repeats,
+        now: &DateTime<Local>,
+    ) -> Option<usize> {
+        let kept = self.recent.len().saturating_sub(repeats.rolls);
+        return self
+            .recent
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(index, roll)| *index >= kept || roll.rolled_at + repeats.window > *now)
+            .find(|(_, roll)| roll.ids.iter().any(|recent| recent == id))
+            .map(|(index, _)| index);
+    }
+
+    /// True if `last` has changed since it was loaded
+    pub fn is_dirty(&self) -> bool {
+        return self.dirty;
+    }
+
+    /// Called once the file has been written back
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+    }
+
+    pub fn from_file(path: &PathBuf) -> Result<Last, TasksError> {
+        return schema::read(path, Kind::Last);
     }
-}
 
-impl Default for Last {
-    fn default() -> Self {
-        Last { last: None }
+    pub fn to_file<'a>(&self, file: &PathBuf) -> Result<(), TasksError> {
+        return schema::write(self, file);
     }
 }
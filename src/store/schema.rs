@@ -0,0 +1,192 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_yml::{Mapping, Value};
+
+use crate::error::TasksError;
+
+/// Schema version written into the header of every store file. Only bumped when older files
+/// need a migration to load: new fields that default when missing do not need one
+pub const VERSION: u64 = 9;
+const VERSION_KEY: &str = "version";
+
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    Bank,
+    Undo,
+    Last,
+}
+
+type Migration = fn(Kind, &mut Mapping);
+
+/// Each migration with the version it upgrades from, oldest first. Versions 1 to 9 differ only
+/// in fields that default when missing, so they need none
+const MIGRATIONS: [(u64, Migration); 1] = [(0, v0_to_v1)];
+
+pub fn read<T: DeserializeOwned>(path: &PathBuf, kind: Kind) -> Result<T, TasksError> {
+    let s = std::fs::read_to_string(path).map_err(TasksError::io(path))?;
+    return parse(&s, kind).map_err(|e| TasksError::parse(path.display(), e));
+}
+
+pub fn write<T: Serialize>(value: &T, file: &PathBuf) -> Result<(), TasksError> {
+    let s = render(value).map_err(|e| TasksError::parse(file.display(), e))?;
+    let mut f = std::fs::File::create(file).map_err(TasksError::io(file))?;
+
+    f.write_all(s.as_bytes()).map_err(TasksError::io(file))?;
+
+    return Ok(());
+}
+
+/// Serializes a store file, with the current version as its first key
+pub fn render<T: Serialize>(value: &T) -> Result<String, String> {
+    let Value::Mapping(body) = serde_yml::to_value(value).map_err(|e| e.to_string())? else {
+        return Err("store files must serialize to a mapping".into());
+    };
+    let mut out = Mapping::new();
+    out.insert(VERSION_KEY.into(), VERSION.into());
+    for (key, value) in body {
+        out.insert(key, value);
+    }
+    return serde_yml::to_string(&out).map_err(|e| e.to_string());
+}
+
+/// Parses a store file, upgrading it to the current schema first
+pub fn parse<T: DeserializeOwned>(s: &str, kind: Kind) -> Result<T, String> {
+    let mut map = match serde_yml::from_str(s).map_err(|e| e.to_string())? {
+        Value::Mapping(map) => map,
+        Value::Null => Mapping::new(),
+        _ => return Err("expected a mapping at the top level".into()),
+    };
+    let version = match map.remove(VERSION_KEY) {
+        None => 0,
+        Some(value) => value
+            .as_u64()
+            .ok_or("`version` must be a non-negative integer")?,
+    };
+    if version > VERSION {
+        return Err(format!(
+            "written with schema version {}, but this version of tasks only understands up \
+             to {}. Please upgrade tasks",
+            version, VERSION
+        ));
+    }
+    for (from, migration) in MIGRATIONS {
+        if version <= from {
+            migration(kind, &mut map);
+        }
+    }
+    return serde_yml::from_value(Value::Mapping(map)).map_err(|e| e.to_string());
+}
+
+// v1 introduced the version header, and makes `tags` and `in_bank` explicit
+fn v0_to_v1(kind: Kind, map: &mut Mapping) {
+    match kind {
+        Kind::Bank => {
+            if let Some(Value::Sequence(tasks)) = map.get_mut("tasks") {
+                tasks.iter_mut().for_each(default_tags);
+            }
+        },
+        Kind::Undo => {
+            if let Some(Value::Sequence(items)) = map.get_mut("undoitems") {
+                items.iter_mut().for_each(v0_to_v1_undo_item);
+            }
+        },
+        Kind::Last => {},
+    }
+}
+
+fn v0_to_v1_undo_item(item: &mut Value) {
+    let Value::Tagged(tagged) = item else {
+        return;
+    };
+    if tagged.tag == "Sequence" {
+        if let Value::Sequence(items) = &mut tagged.value {
+            items.iter_mut().for_each(v0_to_v1_undo_item);
+        }
+        return;
+    }
+    let Value::Mapping(fields) = &mut tagged.value else {
+        return;
+    };
+    if tagged.tag == "Change" && !fields.contains_key("in_bank") {
+        fields.insert("in_bank".into(), "active".into());
+    }
+    for key in ["task", "from", "to", "new_task"] {
+        if let Some(task) = fields.get_mut(key) {
+            default_tags(task);
+        }
+    }
+}
+
+fn default_tags(task: &mut Value) {
+    if let Value::Mapping(task) = task {
+        if !task.contains_key("tags") {
+            task.insert("tags".into(), Value::Sequence(vec![]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::bank::Bank;
+    use crate::store::undobank::{UndoBank, UndoItem};
+
+    fn task(indent: &str) -> String {
+        let lines = [
+            "task: !Todo {}",
+            "metadata:",
+            "  id: '1'",
+            "  description: null",
+            "  name: one",
+            "  priority: 100",
+            "  created: 2024-01-01T00:00:00+00:00",
+            "  last_completed: 2024-01-01T00:00:00+00:00",
+            "  closed_at: null",
+        ];
+        return lines.map(|line| format!("{indent}{line}\n")).concat();
+    }
+
+    #[test]
+    fn unversioned_undo_is_migrated() {
+        let s = format!(
+            "undoitems:\n- !Change\n  from:\n{}  to:\n{}",
+            task("    "),
+            task("    ")
+        );
+        let undo: UndoBank = parse(&s, Kind::Undo).unwrap();
+        match &undo.undoitems[0] {
+            UndoItem::Change { in_bank, to, .. } => {
+                assert_eq!(in_bank, "active");
+                assert_eq!(to.id(), "1");
+            },
+            item => panic!("unexpected item {}", item),
+        }
+    }
+
+    #[test]
+    fn older_versions_load_without_new_fields() {
+        let s = format!("version: 1\ntasks:\n-\n{}", task("  "));
+        let bank: Bank = parse(&s, Kind::Bank).unwrap();
+        assert!(bank.tasks[0].notes().is_empty());
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let s = format!("version: {}\ntasks: []\n", VERSION + 1);
+        let error = parse::<Bank>(&s, Kind::Bank).unwrap_err();
+        assert!(error.contains("upgrade"));
+    }
+
+    #[test]
+    fn round_trip_writes_version_header() {
+        let s = format!("tasks:\n-\n{}", task("  "));
+        let bank: Bank = parse(&s, Kind::Bank).unwrap();
+        let rendered = render(&bank).unwrap();
+        assert!(rendered.starts_with(&format!("version: {}\n", VERSION)));
+        let bank: Bank = parse(&rendered, Kind::Bank).unwrap();
+        assert_eq!(bank.tasks.len(), 1);
+    }
+}
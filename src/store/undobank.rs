@@ -1,48 +1,75 @@
 use core::fmt;
-use std::error::Error;
-use std::io::Write;
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use super::schema::{self, Kind};
+use crate::error::TasksError;
 use crate::task::Task;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct UndoBank {
     pub undoitems: Vec<UndoItem>,
+    #[serde(skip)]
+    dirty:         bool,
 }
 
 impl UndoBank {
+    pub fn new(undoitems: Vec<UndoItem>) -> UndoBank {
+        UndoBank {
+            undoitems,
+            dirty: false,
+        }
+    }
+
     pub fn empty() -> UndoBank {
-        UndoBank { undoitems: vec![] }
+        UndoBank::new(vec![])
+    }
+
+    /// True if the bank has changed since it was loaded
+    pub fn is_dirty(&self) -> bool {
+        return self.dirty;
+    }
+
+    /// Called once the bank has been written back
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
     }
 
-    pub fn from_file(path: &PathBuf) -> Result<UndoBank, Box<dyn Error>> {
-        let s = std::fs::read_to_string(path)?;
-        let bank: UndoBank = serde_yml::from_str(s.as_str())?;
-        return Ok(bank);
+    pub fn from_file(path: &PathBuf) -> Result<UndoBank, TasksError> {
+        return schema::read(path, Kind::Undo);
     }
 
-    pub fn to_file<'a>(&self, file: &PathBuf) -> Result<(), Box<dyn Error>> {
+    pub fn to_file<'a>(&self, file: &PathBuf) -> Result<(), TasksError> {
         let len = self.undoitems.len();
         let mut new_self: UndoBank = self.clone();
         if len > 50 {
             new_self.undoitems = new_self.undoitems[(len - 50)..len].to_vec();
         };
-        let s = serde_yml::to_string(&new_self)?;
-        let mut f = std::fs::File::create(file)?;
-
-        f.write_all(s.as_bytes())?;
-
-        return Ok(());
+        return schema::write(&new_self, file);
     }
 
     pub fn append(&mut self, item: UndoItem) {
-        self.undoitems.push(item)
+        self.undoitems.push(item);
+        self.dirty = true;
+    }
+
+    /// Folds the items appended since there were `len` into one, so that they are undone together
+    pub fn group_since(&mut self, len: usize) {
+        if self.undoitems.len() <= len + 1 {
+            return;
+        }
+        let items = self.undoitems.split_off(len);
+        self.undoitems.push(UndoItem::Sequence(items));
+        self.dirty = true;
     }
 
     pub fn pop(&mut self) -> Option<UndoItem> {
-        self.undoitems.pop()
+        let item = self.undoitems.pop();
+        if item.is_some() {
+            self.dirty = true;
+        }
+        return item;
     }
 }
 
@@ -53,9 +80,10 @@ pub enum UndoItem {
         from: String,
         to:   String,
     },
+    // Boxed, as it holds two tasks to the others' one
     Change {
-        from:    Task,
-        to:      Task,
+        from:    Box<Task>,
+        to:      Box<Task>,
         #[serde(default = "default_bank")]
         in_bank: String,
     },
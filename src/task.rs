@@ -1,8 +1,16 @@
 use core::fmt;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::str::FromStr;
 
 use chrono::{DateTime, Local};
-use colored::Colorize;
 use serde::{Deserialize, Serialize};
+
+use crate::duration;
+use crate::error::TasksError;
+use crate::format::Template;
+use crate::window::Window;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Task {
     task:     TaskType,
@@ -20,6 +28,141 @@ struct Metadata {
     created:        DateTime<Local>,
     last_completed: DateTime<Local>,
     closed_at:      Option<DateTime<Local>>,
+    #[serde(default)]
+    snoozed_until:  Option<DateTime<Local>>,
+    /// Time spent on the task, recorded by `tasks start` and `tasks stop`
+    #[serde(default)]
+    intervals:      Vec<Interval>,
+    /// How long the task is expected to take
+    #[serde(default)]
+    estimate:       Option<Estimate>,
+    /// How much energy the task takes
+    #[serde(default)]
+    energy:         Option<Energy>,
+    /// When the task can be done, instead of the default windows of its tags
+    #[serde(default)]
+    window:         Option<Window>,
+    /// Annotations added over time by `tasks note`, oldest first
+    #[serde(default)]
+    notes:          Vec<Note>,
+}
+
+/// How long a task is expected to take, to the minute. Written like `30m` or `1h 30m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Estimate {
+    minutes: i64,
+}
+
+impl Estimate {
+    pub fn duration(&self) -> chrono::Duration {
+        return chrono::Duration::minutes(self.minutes);
+    }
+}
+
+impl FromStr for Estimate {
+    type Err = TasksError;
+
+    fn from_str(s: &str) -> Result<Estimate, TasksError> {
+        let minutes = duration::parse(s)?.num_minutes();
+        return Ok(Estimate { minutes });
+    }
+}
+
+impl TryFrom<String> for Estimate {
+    type Error = TasksError;
+
+    fn try_from(s: String) -> Result<Estimate, TasksError> {
+        return s.parse();
+    }
+}
+
+impl From<Estimate> for String {
+    fn from(estimate: Estimate) -> String {
+        return estimate.to_string();
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", duration::format(&self.duration()));
+    }
+}
+
+/// How much energy a task takes, or how much is left for one
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Energy {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Energy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Energy::Low => "low",
+            Energy::Medium => "medium",
+            Energy::High => "high",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+/// A timestamped annotation on a task, kept alongside its description
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Note {
+    pub at:   DateTime<Local>,
+    pub text: String,
+}
+
+/// A stretch of time spent working on a task
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Interval {
+    pub start: DateTime<Local>,
+    pub end:   DateTime<Local>,
+    /// What came of it, for sessions of `tasks focus`
+    #[serde(default)]
+    pub focus: Option<Outcome>,
+}
+
+/// How a focus session on a task ended
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Done,
+    Closed,
+    Snoozed { until: DateTime<Local> },
+    Skipped,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Outcome::Done => write!(f, "done"),
+            Outcome::Closed => write!(f, "closed"),
+            Outcome::Snoozed { until } => {
+                write!(f, "snoozed until {}", until.format("%Y-%m-%d %H:%M"))
+            },
+            Outcome::Skipped => write!(f, "skipped"),
+        };
+    }
+}
+
+impl Interval {
+    /// How much of the interval falls after `since`
+    pub fn duration_since(&self, since: Option<&DateTime<Local>>) -> chrono::Duration {
+        let start = match since {
+            Some(since) => cmp::max(self.start, *since),
+            None => self.start,
+        };
+        return cmp::max(
+            self.end.signed_duration_since(start),
+            chrono::Duration::zero(),
+        );
+    }
 }
 
 impl Task {
@@ -36,6 +179,57 @@ impl Task {
         task.metadata.last_completed = now;
         return task;
     }
+    pub fn snoozed(&self, until: DateTime<Local>) -> Self {
+        let mut task = self.clone();
+        task.metadata.snoozed_until = Some(until);
+        return task;
+    }
+    /// Records that the task was worked on from `start` until `end`
+    pub fn tracked(&self, start: DateTime<Local>, end: DateTime<Local>) -> Self {
+        return self.logged(Interval {
+            start,
+            end,
+            focus: None,
+        });
+    }
+    pub fn logged(&self, interval: Interval) -> Self {
+        let mut task = self.clone();
+        task.metadata.intervals.push(interval);
+        return task;
+    }
+    /// Appends a note written now, leaving the description alone
+    pub fn noted(&self, text: &str) -> Self {
+        let mut task = self.clone();
+        task.metadata.notes.push(Note {
+            at:   Local::now(),
+            text: text.to_string(),
+        });
+        return task;
+    }
+    pub fn reopened(&self) -> Self {
+        let mut task = self.clone();
+        task.metadata.closed_at = None;
+        return task;
+    }
+    pub fn closed_at_last_touch(&self) -> Self {
+        let mut task = self.clone();
+        task.metadata.closed_at = Some(task.metadata.last_completed);
+        return task;
+    }
+    pub fn clamped_to(&self, now: &DateTime<Local>) -> Self {
+        let mut task = self.clone();
+        let meta = &mut task.metadata;
+        meta.created = cmp::min(meta.created, *now);
+        meta.last_completed = cmp::min(meta.last_completed, *now);
+        meta.closed_at = meta.closed_at.map(|closed| cmp::min(closed, *now));
+        return task;
+    }
+    pub fn is_from_future(&self, now: &DateTime<Local>) -> bool {
+        let meta = &self.metadata;
+        return meta.created > *now
+            || meta.last_completed > *now
+            || meta.closed_at.is_some_and(|closed| closed > *now);
+    }
 
     pub fn name(&self) -> &str {
         &self.metadata.name
@@ -51,10 +245,92 @@ impl Task {
     pub fn closed(&self) -> &Option<DateTime<Local>> {
         &self.metadata.closed_at
     }
+    pub fn snoozed_until(&self) -> &Option<DateTime<Local>> {
+        &self.metadata.snoozed_until
+    }
+    pub fn is_snoozed(&self, now: &DateTime<Local>) -> bool {
+        return self
+            .metadata
+            .snoozed_until
+            .is_some_and(|until| until > *now);
+    }
+    pub fn intervals(&self) -> &[Interval] {
+        &self.metadata.intervals
+    }
+    pub fn notes(&self) -> &[Note] {
+        &self.metadata.notes
+    }
+    pub fn estimate(&self) -> Option<Estimate> {
+        self.metadata.estimate
+    }
+    pub fn energy(&self) -> Option<Energy> {
+        self.metadata.energy
+    }
+    pub fn with_estimate(&self, estimate: Option<Estimate>) -> Task {
+        let mut out = self.clone();
+        out.metadata.estimate = estimate;
+        return out;
+    }
+    pub fn with_energy(&self, energy: Option<Energy>) -> Task {
+        let mut out = self.clone();
+        out.metadata.energy = energy;
+        return out;
+    }
+    pub fn window(&self) -> Option<Window> {
+        self.metadata.window
+    }
+    pub fn with_window(&self, window: Option<Window>) -> Task {
+        let mut out = self.clone();
+        out.metadata.window = window;
+        return out;
+    }
+    /// Whether the task can be done at `now`: inside its own window or, without one, inside the
+    /// window of any of its tags listed in `defaults`. Tasks without either are always available
+    pub fn is_available(&self, now: &DateTime<Local>, defaults: &BTreeMap<String, Window>) -> bool {
+        if let Some(window) = self.window() {
+            return window.contains(now);
+        }
+        let mut windows = self
+            .tags()
+            .iter()
+            .filter_map(|tag| defaults.get(tag))
+            .peekable();
+        if windows.peek().is_none() {
+            return true;
+        }
+        return windows.any(|window| window.contains(now));
+    }
+    /// Whether the task can be done in `time` with `energy`. Tasks without an estimate or an
+    /// energy level are assumed to fit
+    pub fn fits(&self, time: Option<Estimate>, energy: Option<Energy>) -> bool {
+        let fits_time = match (time, self.estimate()) {
+            (Some(time), Some(estimate)) => estimate <= time,
+            _ => true,
+        };
+        let fits_energy = match (energy, self.energy()) {
+            (Some(energy), Some(needed)) => needed <= energy,
+            _ => true,
+        };
+        return fits_time && fits_energy;
+    }
     pub fn priority(&self) -> &u16 {
         &self.metadata.priority
     }
 
+    /// How strongly random selection favours this task: seconds since it was last touched, times
+    /// its priority. Snoozed tasks weigh nothing
+    pub fn weight(&self, now: &DateTime<Local>) -> u64 {
+        if self.is_snoozed(now) {
+            return 0;
+        }
+        let passed: u64 = now
+            .signed_duration_since(self.last_touched())
+            .num_seconds()
+            .try_into()
+            .unwrap_or(0);
+        return passed * (*self.priority() as u64);
+    }
+
     pub fn description(&self) -> &Option<String> {
         &self.metadata.description
     }
@@ -63,6 +339,14 @@ impl Task {
         &self.metadata.created
     }
 
+    pub fn task_type(&self) -> &TaskType {
+        &self.task
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
     pub fn set_tags(&self, tags: Vec<String>) -> Task {
         let mut out = self.clone();
         out.tags = tags;
@@ -174,6 +458,12 @@ impl Metadata {
             created: now,
             last_completed: now,
             closed_at: None,
+            snoozed_until: None,
+            intervals: vec![],
+            estimate: None,
+            energy: None,
+            window: None,
+            notes: vec![],
         };
     }
     pub fn contains(&self, string: &str) -> bool {
@@ -185,6 +475,9 @@ impl Metadata {
                 return true;
             }
         }
+        if self.notes.iter().any(|note| note.text.contains(string)) {
+            return true;
+        }
         return self.id.contains(string);
     }
 }
@@ -212,72 +505,111 @@ impl Task {
             .any(|str| str == tag);
         return result;
     }
-}
 
-impl fmt::Display for Task {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{} {}\n{} - {}\n",
-            "Id:".italic(),
-            self.metadata.id.to_string().cyan(),
-            self.metadata.name.to_string().bold().yellow(),
-            self.task.to_string().green()
-        )?;
-        match &self.metadata.description {
-            Some(desc) => {
-                let formatted = desc
-                    .split("\\n")
-                    .map(|s| s.trim().to_string().italic().blue());
-                for line in formatted {
-                    write!(f, "{}\n", line)?;
-                }
-            },
-            None => {
-                write!(
-                    f,
-                    "{}\n",
-                    "No description provided.".to_string().italic().blue()
-                )?;
-            },
+    /// Whether the task's name is `name`, or nearly, ignoring case, punctuation and the order of
+    /// words. Nearly is at most one character in five being different
+    pub fn resembles(&self, name: &str) -> bool {
+        let (ours, theirs) = (words(self.name()), words(name));
+        if ours.is_empty() || theirs.is_empty() {
+            return false;
         }
-        let when = chrono::Local::now()
-            .signed_duration_since(&self.metadata.last_completed)
-            .num_hours();
-        write!(f, "{}", "Last Performed: ".italic())?;
-        if when > 24 {
-            let days = when / 24;
-            let when = when % 24;
-            let days_str = if days > 1 { "days, " } else { "day, " };
-            write!(
-                f,
-                "{} {} {} {}",
-                days.to_string().magenta(),
-                days_str.magenta(),
-                when.to_string().magenta(),
-                "hours ago".magenta()
-            )?;
-        } else {
-            write!(
-                f,
-                "{} {}",
-                when.to_string().magenta(),
-                "hours ago".magenta()
-            )?;
+        let (mut sorted_ours, mut sorted_theirs) = (ours.clone(), theirs.clone());
+        sorted_ours.sort();
+        sorted_theirs.sort();
+        if sorted_ours == sorted_theirs {
+            return true;
         }
-        write!(
-            f,
-            "\n{} {}",
-            "Priority:".italic(),
-            self.metadata.priority.to_string().blue()
-        )?;
-        if self.tags.len() > 0 {
-            write!(f, "\n{}", "Tags: ".to_string().yellow())?;
-            write!(f, "{}", self.tags[0])?;
-            for tag in self.tags[1..].iter() {
-                write!(f, ", {}", tag)?;
-            }
+        let (ours, theirs) = (ours.join(" "), theirs.join(" "));
+        let longest = cmp::max(ours.chars().count(), theirs.chars().count());
+        return edit_distance(&ours, &theirs) * 5 <= longest;
+    }
+
+    /// The task with `duplicate` folded in: the tags, description, notes and logged time of both, the
+    /// earlier creation and the later completion. Unset fields are taken from `duplicate`
+    pub fn merged(&self, duplicate: &Task) -> Task {
+        let mut out = self.add_tags(duplicate.tags.clone());
+        let meta = &mut out.metadata;
+        let other = &duplicate.metadata;
+        meta.description = match (&meta.description, &other.description) {
+            // Joined by the literal `\n` that separates the lines of descriptions
+            (Some(ours), Some(theirs)) if ours != theirs => Some(format!("{}\\n{}", ours, theirs)),
+            (None, theirs) => theirs.clone(),
+            (ours, _) => ours.clone(),
+        };
+        meta.created = cmp::min(meta.created, other.created);
+        meta.last_completed = cmp::max(meta.last_completed, other.last_completed);
+        meta.intervals.extend(other.intervals.iter().cloned());
+        meta.intervals.sort_by_key(|interval| interval.start);
+        meta.notes.extend(other.notes.iter().cloned());
+        meta.notes.sort_by_key(|note| note.at);
+        meta.estimate = meta.estimate.or(other.estimate);
+        meta.energy = meta.energy.or(other.energy);
+        meta.window = meta.window.or(other.window);
+        return out;
+    }
+}
+
+// The words of `text`, lowercased, without punctuation
+fn words(text: &str) -> Vec<String> {
+    return text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+}
+
+// Levenshtein distance, in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(cmp::min(
+                substitution,
+                cmp::min(previous[j + 1], current[j]) + 1,
+            ));
         }
-        return Ok(());
+        previous = current;
+    }
+    return previous[b.len()];
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", Template::full().render(self));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resemblance() {
+        let task = Task::new_todo("Water the plants".to_string(), None, None, None);
+        assert!(task.resembles("water the plants!"));
+        assert!(task.resembles("the plants, water"));
+        assert!(task.resembles("water the plant"));
+        assert!(!task.resembles("water the garden"));
+        assert!(!task.resembles(""));
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        let short = Task::new_todo("read".to_string(), None, None, None);
+        assert!(!short.resembles("reed"));
+    }
+
+    #[test]
+    fn notes() {
+        let task = Task::new_todo("Sort the garage".to_string(), Some("shelves"), None, None);
+        let noted = task.noted("cleared the left wall").noted("bins ordered");
+        assert_eq!(noted.description().as_deref(), Some("shelves"));
+        assert_eq!(noted.notes().len(), 2);
+        assert_eq!(noted.notes()[0].text, "cleared the left wall");
+        assert!(noted.contains("left wall"));
+        assert!(!task.contains("left wall"));
+
+        let merged = task.merged(&noted);
+        assert_eq!(merged.notes(), noted.notes());
     }
 }
@@ -0,0 +1,343 @@
+use chrono::{DateTime, Local};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::backend::{Backend, RandomArgs};
+use crate::error::TasksError;
+use crate::parser::ListArgs;
+use crate::task::Task;
+use crate::Config;
+
+const HELP: &str = "j/k move  / filter  d done  c close  e name  E desc  s snooze  t tag  u undo  \
+                    r roll  q quit";
+
+enum Mode {
+    Normal,
+    Filter,
+    Input { field: Field, buffer: String },
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Name,
+    Description,
+    Tags,
+}
+
+impl Field {
+    fn prompt(&self) -> &'static str {
+        match self {
+            Field::Name => "Name: ",
+            Field::Description => "Description: ",
+            Field::Tags => "Tags (+tag adds, -tag removes): ",
+        }
+    }
+}
+
+struct App<'a> {
+    backend: &'a Backend,
+    conf:    &'a Config,
+    filter:  String,
+    tasks:   Vec<Task>,
+    state:   ListState,
+    mode:    Mode,
+    message: String,
+}
+
+/// Runs the full screen interface until the user quits.
+/// Every action goes through `backend`, and is saved straight away, so undo works as on the command line.
+/// The store is only locked while an action runs.
+pub fn run(conf: &Config, backend: &Backend) -> Result<(), TasksError> {
+    let mut app = App {
+        backend,
+        conf,
+        filter: String::new(),
+        tasks: vec![],
+        state: ListState::default(),
+        mode: Mode::Normal,
+        message: String::new(),
+    };
+    app.refresh()?;
+    let mut terminal = ratatui::try_init().map_err(TasksError::Terminal)?;
+    let result = app.run(&mut terminal);
+    ratatui::try_restore().map_err(TasksError::Terminal)?;
+    return result;
+}
+
+impl App<'_> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), TasksError> {
+        loop {
+            terminal
+                .draw(|frame| self.draw(frame))
+                .map_err(TasksError::Terminal)?;
+            let Event::Key(key) = event::read().map_err(TasksError::Terminal)? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let quit = match self.mode {
+                Mode::Normal => self.on_normal_key(key)?,
+                Mode::Filter => self.on_filter_key(key)?,
+                Mode::Input { .. } => self.on_input_key(key)?,
+            };
+            if quit {
+                return Ok(());
+            }
+        }
+    }
+
+    fn selected(&self) -> Option<Task> {
+        return self
+            .state
+            .selected()
+            .and_then(|i| self.tasks.get(i))
+            .cloned();
+    }
+
+    fn terms(&self) -> Vec<String> {
+        return self.filter.split_whitespace().map(String::from).collect();
+    }
+
+    fn refresh(&mut self) -> Result<(), TasksError> {
+        let selected = self.selected().map(|task| task.id().to_string());
+        let args = ListArgs {
+            terms: self.terms(),
+            ..Default::default()
+        };
+        self.tasks = self.backend.list(&args)?;
+        // Other `tasks` runs, like a prompt hook, must not wait for the session to end
+        self.backend.release()?;
+        let index = selected
+            .and_then(|id| self.tasks.iter().position(|task| task.id() == id))
+            .or(self.state.selected())
+            .map(|i| i.min(self.tasks.len().saturating_sub(1)));
+        self.state.select(match self.tasks.is_empty() {
+            true => None,
+            false => index.or(Some(0)),
+        });
+        return Ok(());
+    }
+
+    // Saves after every action, and reports failures in the status line rather than quitting
+    fn act<F>(&mut self, action: F) -> Result<(), TasksError>
+    where
+        F: FnOnce(&Backend, Task) -> Result<String, TasksError>,
+    {
+        let Some(task) = self.selected() else {
+            self.message = "No task selected".to_string();
+            return Ok(());
+        };
+        self.message = match action(self.backend, task).and_then(|message| {
+            self.backend.save()?;
+            Ok(message)
+        }) {
+            Ok(message) => message,
+            Err(error) => error.to_string(),
+        };
+        return self.refresh();
+    }
+
+    fn on_normal_key(&mut self, key: KeyEvent) -> Result<bool, TasksError> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+            KeyCode::Char('j') | KeyCode::Down => self.state.select_next(),
+            KeyCode::Char('k') | KeyCode::Up => self.state.select_previous(),
+            KeyCode::Char('g') | KeyCode::Home => self.state.select_first(),
+            KeyCode::Char('G') | KeyCode::End => self.state.select_last(),
+            KeyCode::Char('/') => self.mode = Mode::Filter,
+            KeyCode::Char('d') => self.act(|backend, task| {
+                let task = backend.touch(&task)?;
+                Ok(format!("Touched `{}`", task.name()))
+            })?,
+            KeyCode::Char('c') => self.act(|backend, task| {
+                let task = backend.close(&task)?;
+                Ok(format!("Closed `{}`", task.name()))
+            })?,
+            KeyCode::Char('s') => {
+                let until = Local::now() + chrono::Duration::seconds(self.conf.snooze as i64);
+                self.act(|backend, task| {
+                    let task = backend.replace(&task.snoozed(until))?;
+                    Ok(format!(
+                        "Snoozed `{}` until {}",
+                        task.name(),
+                        until.format("%c")
+                    ))
+                })?
+            },
+            KeyCode::Char('u') => {
+                self.message = match self.backend.undo().and_then(|item| {
+                    self.backend.save()?;
+                    Ok(item)
+                }) {
+                    Ok(_) => "Undone".to_string(),
+                    Err(error) => error.to_string(),
+                };
+                self.refresh()?;
+            },
+            KeyCode::Char('r') => self.roll()?,
+            KeyCode::Char(c @ ('e' | 'E' | 't')) => {
+                if let Some(task) = self.selected() {
+                    let (field, buffer) = match c {
+                        'e' => (Field::Name, task.name().to_string()),
+                        'E' => (
+                            Field::Description,
+                            task.description().clone().unwrap_or_default(),
+                        ),
+                        _ => (Field::Tags, String::new()),
+                    };
+                    self.mode = Mode::Input { field, buffer };
+                }
+            },
+            _ => {},
+        }
+        return Ok(false);
+    }
+
+    fn on_filter_key(&mut self, key: KeyEvent) -> Result<bool, TasksError> {
+        match key.code {
+            KeyCode::Enter | KeyCode::Esc => self.mode = Mode::Normal,
+            KeyCode::Backspace => {
+                self.filter.pop();
+            },
+            KeyCode::Char(c) => self.filter.push(c),
+            _ => return Ok(false),
+        }
+        self.refresh()?;
+        return Ok(false);
+    }
+
+    fn on_input_key(&mut self, key: KeyEvent) -> Result<bool, TasksError> {
+        let Mode::Input { field, buffer } = &mut self.mode else {
+            return Ok(false);
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = Mode::Normal,
+            KeyCode::Backspace => {
+                buffer.pop();
+            },
+            KeyCode::Char(c) => buffer.push(c),
+            KeyCode::Enter => {
+                let field = *field;
+                let value = buffer.clone();
+                self.mode = Mode::Normal;
+                self.act(|backend, task| {
+                    let task = backend.replace(&edited(&task, field, &value))?;
+                    Ok(format!("Edited `{}`", task.name()))
+                })?;
+            },
+            _ => {},
+        }
+        return Ok(false);
+    }
+
+    fn roll(&mut self) -> Result<(), TasksError> {
+        // The same filter as the list, so that the roll is one of the tasks shown
+        let chosen = self.backend.random(&RandomArgs {
+            terms: self.terms(),
+            n: 1,
+            ..Default::default()
+        })?;
+        self.backend.release()?;
+        match chosen.first() {
+            Some(task) => {
+                let index = self.tasks.iter().position(|other| other.id() == task.id());
+                self.state.select(index);
+                self.message = format!("Rolled `{}`", task.name());
+            },
+            None => self.message = "Nothing to roll, every task is too recent".to_string(),
+        }
+        return Ok(());
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(2)]).areas(frame.area());
+        let [list, detail] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+
+        let title = match self.filter.is_empty() {
+            true => format!(" Tasks ({}) ", self.tasks.len()),
+            false => format!(" Tasks ({}) matching `{}` ", self.tasks.len(), self.filter),
+        };
+        let items: Vec<ListItem> = self
+            .tasks
+            .iter()
+            .map(|task| ListItem::new(task.name().to_string()))
+            .collect();
+        let tasks = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(tasks, list, &mut self.state);
+
+        let lines = match self.selected() {
+            Some(task) => detail_lines(&task),
+            None => vec![Line::from("No task selected".italic())],
+        };
+        let details = Paragraph::new(lines)
+            .block(Block::bordered().title(" Details "))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(details, detail);
+
+        let prompt = match &self.mode {
+            Mode::Normal => Line::from(self.message.as_str()),
+            Mode::Filter => Line::from(format!("/{}", self.filter)),
+            Mode::Input { field, buffer } => Line::from(format!("{}{}", field.prompt(), buffer)),
+        };
+        let help = Line::from(HELP.dark_gray());
+        frame.render_widget(Paragraph::new(vec![prompt, help]), status);
+    }
+}
+
+fn edited(task: &Task, field: Field, value: &str) -> Task {
+    match field {
+        Field::Name => task.updated_todo(None, None, Some(value)),
+        Field::Description => task.updated_todo(Some(value), None, None),
+        Field::Tags => {
+            let mut task = task.clone();
+            for word in value.split([' ', ',']).filter(|word| !word.is_empty()) {
+                task = match word.strip_prefix('-') {
+                    Some(tag) => task.remove_tags(&vec![tag.to_string()]),
+                    None => task.add_tags(vec![word.trim_start_matches('+').to_string()]),
+                };
+            }
+            task
+        },
+    }
+}
+
+fn detail_lines(task: &Task) -> Vec<Line<'static>> {
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<15}", name), Style::new().italic()),
+            Span::raw(value),
+        ])
+    };
+    let when = |time: &DateTime<Local>| time.format("%Y-%m-%d %H:%M").to_string();
+    let mut lines = vec![
+        Line::from(task.name().to_string().bold().fg(Color::Yellow)),
+        field("Id:", task.id().to_string()),
+        field("Type:", task.task_type().to_string()),
+        field("Priority:", task.priority().to_string()),
+        field("Tags:", task.tags().join(", ")),
+        field("Created:", when(task.created())),
+        field("Last performed:", when(task.last_touched())),
+    ];
+    if let Some(until) = task.snoozed_until() {
+        lines.push(field("Snoozed until:", when(until)));
+    }
+    lines.push(Line::default());
+    match task.description() {
+        Some(desc) => lines.extend(
+            desc.split("\\n")
+                .map(|line| Line::from(line.trim().to_string().fg(Color::Blue))),
+        ),
+        None => lines.push(Line::from("No description provided.".italic())),
+    }
+    return lines;
+}
@@ -0,0 +1,232 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::error::TasksError;
+
+// One bit per day, Monday first
+const ALL_DAYS: u8 = 0b111_1111;
+const WEEKDAYS: u8 = 0b001_1111;
+const WEEKENDS: u8 = 0b110_0000;
+
+/// When a task can be done: some days of the week, some hours of the day, or both. Written like
+/// `weekdays 9-17`, `weekends`, `mon,wed,fri 18:30-21`, `22-2`, or `always`.
+///
+/// Hours that wrap past midnight belong to the day they start on, so `fri 22-2` includes the
+/// small hours of Saturday.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Window {
+    days:  u8,
+    // Minutes since midnight
+    hours: Option<(u16, u16)>,
+}
+
+impl Window {
+    /// Whether `time` falls in the window
+    pub fn contains(&self, time: &DateTime<Local>) -> bool {
+        let Some((start, end)) = self.hours else {
+            return self.has_day(time.weekday());
+        };
+        let now = minutes(&time.time());
+        if start < end {
+            return self.has_day(time.weekday()) && start <= now && now < end;
+        }
+        // Wraps past midnight
+        if now >= start {
+            return self.has_day(time.weekday());
+        }
+        return now < end && self.has_day((*time - Duration::days(1)).weekday());
+    }
+
+    fn has_day(&self, day: Weekday) -> bool {
+        return self.days & day_bit(day) != 0;
+    }
+}
+
+fn day_bit(day: Weekday) -> u8 {
+    return 1 << day.num_days_from_monday();
+}
+
+impl FromStr for Window {
+    type Err = TasksError;
+
+    fn from_str(s: &str) -> Result<Window, TasksError> {
+        let invalid = || {
+            TasksError::parse(
+                "window",
+                format!(
+                    "`{}` is not a window like `weekdays 9-17`, `weekends` or `sat,sun 10-18`",
+                    s
+                ),
+            )
+        };
+        let mut days = 0;
+        let mut hours = None;
+        for word in s.split_whitespace() {
+            if word.eq_ignore_ascii_case("always") {
+                days |= ALL_DAYS;
+            } else if word.starts_with(|c: char| c.is_ascii_digit()) {
+                if hours.is_some() {
+                    return Err(invalid());
+                }
+                hours = Some(parse_hours(word).ok_or_else(invalid)?);
+            } else {
+                for part in word.split(',').filter(|part| !part.is_empty()) {
+                    days |= parse_days(part).ok_or_else(invalid)?;
+                }
+            }
+        }
+        if days == 0 && hours.is_none() {
+            return Err(invalid());
+        }
+        if days == 0 {
+            days = ALL_DAYS;
+        }
+        return Ok(Window { days, hours });
+    }
+}
+
+// `weekdays`, `weekends`, a day like `mon` or `monday`, or a range of them like `fri-sun`
+fn parse_days(text: &str) -> Option<u8> {
+    match text.to_lowercase().as_str() {
+        "weekdays" => return Some(WEEKDAYS),
+        "weekends" => return Some(WEEKENDS),
+        "daily" => return Some(ALL_DAYS),
+        _ => {},
+    }
+    let Some((first, last)) = text.split_once('-') else {
+        return text.parse().ok().map(day_bit);
+    };
+    let mut day: Weekday = first.parse().ok()?;
+    let last: Weekday = last.parse().ok()?;
+    let mut days = day_bit(day);
+    while day != last {
+        day = day.succ();
+        days |= day_bit(day);
+    }
+    return Some(days);
+}
+
+// `9-17`, `09:30-17:00`, or `22-2` across midnight. `24` ends at midnight
+fn parse_hours(text: &str) -> Option<(u16, u16)> {
+    let (start, end) = text.split_once('-')?;
+    let time = |text: &str, midnight: u16| -> Option<u16> {
+        if text == "24" || text == "24:00" {
+            return Some(midnight);
+        }
+        let time = match text.parse() {
+            Ok(hour) => NaiveTime::from_hms_opt(hour, 0, 0)?,
+            Err(_) => NaiveTime::parse_from_str(text, "%H:%M").ok()?,
+        };
+        return Some(minutes(&time));
+    };
+    let (start, end) = (time(start, 0)?, time(end, 24 * 60)?);
+    if start == end {
+        return None;
+    }
+    return Some((start, end));
+}
+
+fn minutes(time: &NaiveTime) -> u16 {
+    return (time.hour() * 60 + time.minute()) as u16;
+}
+
+impl TryFrom<String> for Window {
+    type Error = TasksError;
+
+    fn try_from(s: String) -> Result<Window, TasksError> {
+        return s.parse();
+    }
+}
+
+impl From<Window> for String {
+    fn from(window: Window) -> String {
+        return window.to_string();
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = match self.days {
+            ALL_DAYS => None,
+            WEEKDAYS => Some("weekdays".to_string()),
+            WEEKENDS => Some("weekends".to_string()),
+            days => {
+                let names: Vec<String> = (0..7u8)
+                    .filter(|day| days & (1 << day) != 0)
+                    .filter_map(|day| Weekday::try_from(day).ok())
+                    .map(|day| day.to_string().to_lowercase())
+                    .collect();
+                Some(names.join(","))
+            },
+        };
+        let hours = self.hours.map(|(start, end)| {
+            format!(
+                "{:02}:{:02}-{:02}:{:02}",
+                start / 60,
+                start % 60,
+                end / 60,
+                end % 60
+            )
+        });
+        return match (days, hours) {
+            (Some(days), Some(hours)) => write!(f, "{} {}", days, hours),
+            (Some(days), None) => write!(f, "{}", days),
+            (None, Some(hours)) => write!(f, "{}", hours),
+            (None, None) => write!(f, "always"),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn windows() {
+        // A Saturday
+        let at = |day, hour, minute| {
+            Local
+                .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+                .unwrap()
+        };
+        let work: Window = "weekdays 9-17".parse().unwrap();
+        assert!(!work.contains(&at(15, 10, 0)));
+        assert!(work.contains(&at(17, 9, 0)));
+        assert!(!work.contains(&at(17, 17, 0)));
+        assert_eq!(work.to_string(), "weekdays 09:00-17:00");
+
+        let late: Window = "FRI 22-2:30".parse().unwrap();
+        assert!(late.contains(&at(14, 23, 0)));
+        assert!(late.contains(&at(15, 2, 15)));
+        assert!(!late.contains(&at(15, 23, 0)));
+        assert!(!late.contains(&at(14, 2, 0)));
+
+        let garden: Window = "sat,sun".parse().unwrap();
+        assert_eq!(garden.to_string(), "weekends");
+        assert!(garden.contains(&at(16, 12, 0)));
+        assert_eq!(
+            "fri-mon".parse::<Window>().unwrap().to_string(),
+            "mon,fri,sat,sun"
+        );
+        assert_eq!("always".parse::<Window>().unwrap().to_string(), "always");
+        assert_eq!(
+            "18-24".parse::<Window>().unwrap().to_string(),
+            "18:00-24:00"
+        );
+        let day: Window = "0-24".parse().unwrap();
+        assert_eq!(day.to_string(), "00:00-24:00");
+        assert!(day.contains(&at(15, 0, 0)));
+        assert!(day.contains(&at(15, 23, 59)));
+        assert_eq!(day.to_string().parse::<Window>().unwrap(), day);
+
+        for invalid in ["", "someday", "9", "9-9", "9-17 10-12", "mon-", "25-3"] {
+            assert!(invalid.parse::<Window>().is_err(), "{}", invalid);
+        }
+    }
+}